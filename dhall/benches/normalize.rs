@@ -0,0 +1,66 @@
+#![feature(test)]
+extern crate test;
+
+use dhall::expr::{Parsed, Typed};
+use test::Bencher;
+
+fn typed(s: &str) -> Typed {
+    Parsed::parse_str(s)
+        .unwrap()
+        .resolve()
+        .unwrap()
+        .skip_typecheck()
+}
+
+/// A chain of lets where each binding uses the previous one
+fn let_chain() -> Typed {
+    let lets: String = (1..200)
+        .map(|i| format!("let x{} = x{} + 1\n", i, i - 1))
+        .collect();
+    typed(&format!("let x0 = 0\n{}in  x199", lets))
+}
+
+/// Prelude-style list functions defined with folds and applied to a list
+fn prelude_like() -> Typed {
+    let items: Vec<_> = (0..100).map(|i| i.to_string()).collect();
+    typed(&format!(
+        r#"
+let map =
+      λ(a : Type)
+    → λ(b : Type)
+    → λ(f : a → b)
+    → λ(xs : List a)
+    → List/build
+      b
+      (   λ(list : Type)
+        → λ(cons : b → list → list)
+        → List/fold a xs list (λ(x : a) → cons (f x))
+      )
+let sum =
+      λ(xs : List Natural)
+    → List/fold
+      Natural
+      xs
+      Natural
+      (λ(x : Natural) → λ(acc : Natural) → x + acc)
+      0
+let double =
+      λ(n : Natural)
+    → Natural/fold n Natural (λ(x : Natural) → x + 2) 0
+in  sum (map Natural Natural double [ {} ])
+"#,
+        items.join(", ")
+    ))
+}
+
+#[bench]
+fn bench_normalize_let_chain(b: &mut Bencher) {
+    let e = let_chain();
+    b.iter(|| e.clone().normalize())
+}
+
+#[bench]
+fn bench_normalize_prelude_like(b: &mut Bencher) {
+    let e = prelude_like();
+    b.iter(|| e.clone().normalize())
+}
@@ -0,0 +1,37 @@
+#![feature(test)]
+extern crate test;
+
+use dhall::expr::{Parsed, Resolved};
+use test::Bencher;
+
+fn resolved(s: &str) -> Resolved {
+    Parsed::parse_str(s).unwrap().resolve().unwrap()
+}
+
+/// Nested lambdas whose body uses the outermost variable
+fn nested_lambdas() -> Resolved {
+    let binders: String = (0..500)
+        .map(|i| format!("λ(x{} : Natural) → ", i))
+        .collect();
+    resolved(&format!("{}x0", binders))
+}
+
+/// Nested lambdas that all bind the same name
+fn shadowing_lambdas() -> Resolved {
+    let binders: String = (0..500)
+        .map(|_| "λ(x : { a : Natural, b : Bool }) → ".to_owned())
+        .collect();
+    resolved(&format!("{}x@499", binders))
+}
+
+#[bench]
+fn bench_typecheck_nested_lambdas(b: &mut Bencher) {
+    let e = nested_lambdas();
+    b.iter(|| e.clone().typecheck().unwrap())
+}
+
+#[bench]
+fn bench_typecheck_shadowing_lambdas(b: &mut Bencher) {
+    let e = shadowing_lambdas();
+    b.iter(|| e.clone().typecheck().unwrap())
+}
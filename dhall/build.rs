@@ -35,10 +35,6 @@ fn main() -> std::io::Result<()> {
     for path in dhall_files_in_dir(&tests_dir.join("parser/success/")) {
         let path = &path[..path.len() - 1];
         let name = path.replace("/", "_");
-        // Skip this test; parser is way too slow indebug mode
-        if name == "largeExpression" {
-            continue;
-        }
         writeln!(
             file,
             r#"make_spec_test!(Parser, Success, success_{}, "{}");"#,
@@ -55,5 +51,25 @@ fn main() -> std::io::Result<()> {
         )?;
     }
 
+    let alpha_normalization_tests_path =
+        Path::new(&out_dir).join("alpha_normalization_tests.rs");
+    let mut file = File::create(alpha_normalization_tests_path)?;
+
+    for path in
+        dhall_files_in_dir(&tests_dir.join("alpha-normalization/success/"))
+    {
+        // Each test comes with an A and a B file
+        if !path.ends_with("A") {
+            continue;
+        }
+        let path = &path[..path.len() - 1];
+        let name = path.replace("/", "_");
+        writeln!(
+            file,
+            r#"make_spec_test!(AlphaNormalization, Success, success_{}, "{}");"#,
+            name, path
+        )?;
+    }
+
     Ok(())
 }
@@ -1,19 +1,160 @@
 use dhall_core::*;
 use itertools::*;
-use serde_cbor::value::value as cbor;
 
 type ParsedExpr = SubExpr<X, Import>;
 
 #[derive(Debug)]
 pub enum DecodeError {
-    CBORError(serde_cbor::error::Error),
+    CBORError(String),
     WrongFormatError(String),
 }
 
 pub fn decode(data: &[u8]) -> Result<ParsedExpr, DecodeError> {
-    match serde_cbor::de::from_slice(data) {
-        Ok(v) => cbor_value_to_dhall(&v),
-        Err(e) => Err(DecodeError::CBORError(e)),
+    let v = cbor::from_slice(data)?;
+    cbor_value_to_dhall(&v)
+}
+
+/// A minimal CBOR reader. Unlike serde_cbor it keeps the tags, which tell
+/// positive and negative bignums apart.
+mod cbor {
+    use super::DecodeError;
+    use std::collections::BTreeMap;
+
+    /// How deeply arrays, maps and tags may be nested, as in serde_cbor
+    const MAX_DEPTH: usize = 128;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Value {
+        U64(u64),
+        /// The negative integer `-1 - n`
+        Neg(u64),
+        F64(f64),
+        Bool(bool),
+        Null,
+        Bytes(Vec<u8>),
+        String(String),
+        Array(Vec<Value>),
+        Object(BTreeMap<String, Value>),
+        Tag(u64, Box<Value>),
+    }
+
+    impl Value {
+        pub fn as_string(&self) -> Option<&String> {
+            match self {
+                Value::String(s) => Some(s),
+                _ => None,
+            }
+        }
+    }
+
+    fn err<T>(msg: &str) -> Result<T, DecodeError> {
+        Err(DecodeError::CBORError(msg.to_owned()))
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Value, DecodeError> {
+        let mut data = data;
+        let v = read_value(&mut data, 0)?;
+        if !data.is_empty() {
+            return err("trailing data");
+        }
+        Ok(v)
+    }
+
+    fn take<'a>(data: &mut &'a [u8], n: u64) -> Result<&'a [u8], DecodeError> {
+        if (data.len() as u64) < n {
+            return err("unexpected end of input");
+        }
+        let (head, tail) = data.split_at(n as usize);
+        *data = tail;
+        Ok(head)
+    }
+
+    fn read_uint(data: &mut &[u8], n: u64) -> Result<u64, DecodeError> {
+        Ok(take(data, n)?
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | u64::from(*b)))
+    }
+
+    fn f16_to_f64(h: u64) -> f64 {
+        let exp = (h >> 10) & 0x1f;
+        let mant = (h & 0x3ff) as f64;
+        let x = match exp {
+            0 => mant * 2f64.powi(-24),
+            31 if h & 0x3ff == 0 => std::f64::INFINITY,
+            31 => std::f64::NAN,
+            _ => (mant + 1024.0) * 2f64.powi(exp as i32 - 25),
+        };
+        if h & 0x8000 != 0 {
+            -x
+        } else {
+            x
+        }
+    }
+
+    fn read_value(
+        data: &mut &[u8],
+        depth: usize,
+    ) -> Result<Value, DecodeError> {
+        let initial = take(data, 1)?[0];
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+        if major == 7 {
+            return Ok(match info {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 => Value::Null,
+                25 => Value::F64(f16_to_f64(read_uint(data, 2)?)),
+                26 => Value::F64(f64::from(f32::from_bits(
+                    read_uint(data, 4)? as u32,
+                ))),
+                27 => Value::F64(f64::from_bits(read_uint(data, 8)?)),
+                _ => return err("unsupported simple value"),
+            });
+        }
+        let arg = match info {
+            0..=23 => u64::from(info),
+            24 => read_uint(data, 1)?,
+            25 => read_uint(data, 2)?,
+            26 => read_uint(data, 4)?,
+            27 => read_uint(data, 8)?,
+            31 => return err("indefinite-length items are not supported"),
+            _ => return err("invalid additional information"),
+        };
+        if major >= 4 && depth >= MAX_DEPTH {
+            return err("nesting too deep");
+        }
+        let depth = depth + 1;
+        Ok(match major {
+            0 => Value::U64(arg),
+            1 => Value::Neg(arg),
+            2 => Value::Bytes(take(data, arg)?.to_vec()),
+            3 => match std::str::from_utf8(take(data, arg)?) {
+                Ok(s) => Value::String(s.to_owned()),
+                Err(_) => return err("invalid UTF-8 in text string"),
+            },
+            4 => Value::Array(
+                (0..arg)
+                    .map(|_| read_value(data, depth))
+                    .collect::<Result<_, _>>()?,
+            ),
+            5 => Value::Object(
+                (0..arg)
+                    .map(|_| match read_value(data, depth)? {
+                        Value::String(k) => Ok((k, read_value(data, depth)?)),
+                        _ => err("map keys must be text strings"),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => Value::Tag(arg, Box::new(read_value(data, depth)?)),
+        })
+    }
+}
+
+/// The magnitude of a bignum, as big-endian bytes
+fn bignum_bytes(data: &cbor::Value) -> Result<&[u8], DecodeError> {
+    match data {
+        cbor::Value::Bytes(bytes) => Ok(bytes),
+        _ => Err(DecodeError::WrongFormatError("bignum".to_owned())),
     }
 }
 
@@ -94,6 +235,9 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<ParsedExpr, DecodeError> {
                 };
                 BinOp(op, x, y)
             }
+            [U64(4), Null] => {
+                Err(DecodeError::WrongFormatError("list/empty".to_owned()))?
+            }
             [U64(4), t] => {
                 let t = cbor_value_to_dhall(&t)?;
                 EmptyListLit(t)
@@ -158,9 +302,20 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<ParsedExpr, DecodeError> {
                 let z = cbor_value_to_dhall(&z)?;
                 BoolIf(x, y, z)
             }
-            [U64(15), U64(x)] => NaturalLit(*x as Natural),
-            [U64(16), U64(x)] => IntegerLit(*x as Integer),
-            [U64(16), I64(x)] => IntegerLit(*x as Integer),
+            [U64(15), U64(x)] => NaturalLit(Natural::from(*x)),
+            [U64(15), Tag(2, n)] => {
+                NaturalLit(Natural::from_bytes_be(bignum_bytes(n)?))
+            }
+            [U64(16), U64(x)] => IntegerLit(Integer::from(*x)),
+            [U64(16), Neg(x)] => IntegerLit(-1 - Integer::from(*x)),
+            [U64(16), Tag(2, n)] => {
+                let n = Natural::from_bytes_be(bignum_bytes(n)?);
+                IntegerLit(Integer::from(n))
+            }
+            [U64(16), Tag(3, n)] => {
+                let n = Natural::from_bytes_be(bignum_bytes(n)?);
+                IntegerLit(-1 - Integer::from(n))
+            }
             [U64(18), String(first), rest..] => {
                 TextLit(InterpolatedText::from((
                     first.clone(),
@@ -330,15 +485,83 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<ParsedExpr, DecodeError> {
 }
 
 fn cbor_map_to_dhall_map(
-    map: &std::collections::BTreeMap<cbor::ObjectKey, cbor::Value>,
+    map: &std::collections::BTreeMap<String, cbor::Value>,
 ) -> Result<std::collections::BTreeMap<Label, ParsedExpr>, DecodeError> {
     map.iter()
         .map(|(k, v)| -> Result<(_, _), _> {
-            let k = k.as_string().ok_or_else(|| {
-                DecodeError::WrongFormatError("map/key".to_owned())
-            })?;
             let v = cbor_value_to_dhall(v)?;
             Ok((Label::from(k.as_ref()), v))
         })
         .collect::<Result<_, _>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_bignums() {
+        let two_to_the_64 = [0x49, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        let cases = [
+            (0x0f, 0xc2, "18446744073709551616"),
+            (0x10, 0xc2, "+18446744073709551616"),
+            (0x10, 0xc3, "-18446744073709551617"),
+        ];
+        for (label, tag, text) in cases.iter() {
+            let mut data = vec![0x82, *label, *tag];
+            data.extend_from_slice(&two_to_the_64);
+            let decoded = decode(&data).unwrap();
+            assert_eq!(decoded, parse_expr(text).unwrap().unnote());
+        }
+        // A negative bignum tag isn't a valid Natural
+        let mut data = vec![0x82, 0x0f, 0xc3];
+        data.extend_from_slice(&two_to_the_64);
+        assert!(decode(&data).is_err());
+    }
+
+    #[test]
+    fn test_decode_integer_bounds() {
+        let cases: &[(u8, u64, &str)] = &[
+            (0x0f, u64::max_value(), "18446744073709551615"),
+            (0x10, i64::max_value() as u64, "-9223372036854775808"),
+            (0x10, 1 << 63, "-9223372036854775809"),
+            (0x10, u64::max_value(), "-18446744073709551616"),
+        ];
+        for (label, arg, text) in cases {
+            // A positive integer for Natural, a negative one for Integer
+            let major = if *label == 0x0f { 0x1b } else { 0x3b };
+            let mut data = vec![0x82, *label, major];
+            data.extend_from_slice(&arg.to_be_bytes());
+            let decoded = decode(&data).unwrap();
+            assert_eq!(decoded, parse_expr(text).unwrap().unnote());
+        }
+    }
+
+    #[test]
+    fn test_decode_nesting() {
+        // `Some` nested `depth` times around `1`
+        let nested = |depth| {
+            let mut data = vec![];
+            for _ in 0..depth {
+                data.extend_from_slice(&[0x83, 0x05, 0xf6]);
+            }
+            data.extend_from_slice(&[0x82, 0x0f, 0x01]);
+            decode(&data)
+        };
+        assert!(nested(100).is_ok());
+        match nested(100_000) {
+            Err(DecodeError::CBORError(msg)) => {
+                assert_eq!(msg, "nesting too deep")
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_decode_empty_non_empty_list() {
+        match decode(&[0x82, 0x04, 0xf6]) {
+            Err(DecodeError::WrongFormatError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Cache of typechecking results.
+//!
+//! Every file loaded as an import is typechecked once it is resolved, and
+//! embedded with its type in the files that import it. The results are
+//! remembered here for each file, so that loading a file that has not changed
+//! does not typecheck it again, even when a file next to it has changed.
+//!
+//! They are keyed by a hash of the source of the file and of the keys of its
+//! own imports, computed as the file is loaded: a change in any of its imports
+//! gives a different key. At most `CAPACITY` results are kept; the oldest one
+//! is forgotten when a new one would not fit.
+use crate::expr::*;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+/// The number of typechecking results kept on each thread
+const CAPACITY: usize = 1024;
+
+/// A hash of the source of a file and of everything it imports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct ContentHash(u64, u64);
+
+impl ContentHash {
+    /// The key of a file with the given source, whose imports have the given
+    /// keys in the order they appear
+    pub(crate) fn new(source: &str, imports: &[ContentHash]) -> Self {
+        // Two differently seeded hashes, to make collisions unlikely
+        let hash = |seed: u8| {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            source.hash(&mut hasher);
+            imports.hash(&mut hasher);
+            hasher.finish()
+        };
+        ContentHash(hash(0), hash(1))
+    }
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<ContentHash, Typed>,
+    /// The keys of `entries`, oldest first
+    order: VecDeque<ContentHash>,
+    /// How many lookups found nothing
+    #[cfg(test)]
+    misses: usize,
+}
+
+thread_local! {
+    static CACHE: RefCell<Cache> = RefCell::new(Cache::default());
+}
+
+/// The result of a previous typechecking of the file with this key, if any
+pub(crate) fn lookup(key: ContentHash) -> Option<Typed> {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let found = cache.entries.get(&key).cloned();
+        #[cfg(test)]
+        {
+            if found.is_none() {
+                cache.misses += 1;
+            }
+        }
+        found
+    })
+}
+
+pub(crate) fn insert(key: ContentHash, e: &Typed) {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.entries.insert(key, e.clone()).is_none() {
+            cache.order.push_back(key);
+            if cache.order.len() > CAPACITY {
+                if let Some(oldest) = cache.order.pop_front() {
+                    cache.entries.remove(&oldest);
+                }
+            }
+        }
+    })
+}
+
+/// Forgets the typechecking results cached on the current thread
+pub fn clear_typecheck_cache() {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.entries.clear();
+        cache.order.clear();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn misses() -> usize {
+        CACHE.with(|cache| cache.borrow().misses)
+    }
+
+    #[test]
+    fn test_unchanged_import() {
+        let dir = std::env::temp_dir()
+            .join(format!("dhall-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, s: &str| fs::write(dir.join(name), s).unwrap();
+        let load = || {
+            Parsed::parse_file(&dir.join("root.dhall"))
+                .unwrap()
+                .resolve()
+                .unwrap()
+                .typecheck()
+                .unwrap()
+                .normalize()
+        };
+        write("root.dhall", "{ a = ./a.dhall, b = ./b.dhall }");
+        write("a.dhall", "λ(x : Natural) → x + 1");
+        write("b.dhall", "True");
+
+        clear_typecheck_cache();
+        let before = misses();
+        load();
+        // Both imports were typechecked
+        assert_eq!(misses(), before + 2);
+
+        load();
+        // Neither import was typechecked again
+        assert_eq!(misses(), before + 2);
+
+        write("b.dhall", "1");
+        let root = load();
+        // Only the changed import was typechecked again
+        assert_eq!(misses(), before + 3);
+        let expected =
+            Parsed::parse_str("{ a = λ(x : Natural) → x + 1, b = 1 }")
+                .unwrap()
+                .resolve()
+                .unwrap()
+                .typecheck()
+                .unwrap()
+                .normalize();
+        assert_eq!(root, expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_capacity() {
+        clear_typecheck_cache();
+        let typed = Parsed::parse_str("True")
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap();
+        let key = |i: usize| ContentHash::new(&i.to_string(), &[]);
+        for i in 0..=CAPACITY {
+            insert(key(i), &typed);
+        }
+        assert_eq!(lookup(key(0)), None);
+        assert_eq!(lookup(key(1)), Some(typed.clone()));
+        assert_eq!(lookup(key(CAPACITY)), Some(typed));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::expr::Normalized;
+use dhall_core::*;
+use std::fmt;
+
+/// One difference between the type an expression was expected to have and
+/// the type it has. Each difference is found at a path of record fields and
+/// union alternatives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeDiff {
+    /// Only the expected type has something at this path
+    Missing(Vec<Label>, SubExpr<X, X>),
+    /// Only the actual type has something at this path
+    Extra(Vec<Label>, SubExpr<X, X>),
+    /// The expected and the actual type at this path
+    Changed(Vec<Label>, SubExpr<X, X>, SubExpr<X, X>),
+}
+
+/// Compares two normalized types, descending into record and union types to
+/// report only the parts that differ
+pub fn diff_types(
+    expected: &SubExpr<X, X>,
+    actual: &SubExpr<X, X>,
+) -> Vec<TypeDiff> {
+    let mut diffs = vec![];
+    diff_at(&mut vec![], expected, actual, &mut diffs);
+    diffs
+}
+
+fn diff_at(
+    path: &mut Vec<Label>,
+    expected: &SubExpr<X, X>,
+    actual: &SubExpr<X, X>,
+    diffs: &mut Vec<TypeDiff>,
+) {
+    use dhall_core::ExprF::*;
+    use TypeDiff::*;
+    let alpha =
+        |e: &SubExpr<X, X>| Normalized(e.clone(), None).alpha_normalize();
+    if alpha(expected) == alpha(actual) {
+        return;
+    }
+    match (expected.as_ref(), actual.as_ref()) {
+        (RecordType(kts1), RecordType(kts2))
+        | (UnionType(kts1), UnionType(kts2)) => {
+            for (k, t1) in kts1 {
+                path.push(k.clone());
+                match kts2.get(k) {
+                    Some(t2) => diff_at(path, t1, t2, diffs),
+                    None => diffs.push(Missing(path.clone(), t1.clone())),
+                }
+                path.pop();
+            }
+            for (k, t2) in kts2 {
+                if !kts1.contains_key(k) {
+                    path.push(k.clone());
+                    diffs.push(Extra(path.clone(), t2.clone()));
+                    path.pop();
+                }
+            }
+        }
+        _ => {
+            diffs.push(Changed(path.clone(), expected.clone(), actual.clone()))
+        }
+    }
+}
+
+impl fmt::Display for TypeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show_path = |path: &[Label]| {
+            let path: Vec<_> = path.iter().map(|l| l.to_string()).collect();
+            path.join(".")
+        };
+        match self {
+            TypeDiff::Missing(path, t) => {
+                write!(f, "- {} : {} is missing", show_path(path), t)
+            }
+            TypeDiff::Extra(path, t) => {
+                write!(f, "+ {} : {} is unexpected", show_path(path), t)
+            }
+            TypeDiff::Changed(path, t1, t2) => write!(
+                f,
+                "~ {} : expected {} but found {}",
+                show_path(path),
+                t1,
+                t2
+            ),
+        }
+    }
+}
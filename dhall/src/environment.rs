@@ -0,0 +1,61 @@
+use crate::expr::*;
+use crate::typecheck::TypeError;
+use dhall_core::*;
+
+/// Named values supplied by the host application, that an expression can
+/// refer to as free variables.
+///
+/// ```edition2018
+/// use dhall::expr::{Normalized, Parsed};
+/// use dhall::{Deserialize, Environment};
+///
+/// let env = Environment::new()
+///     .insert("region", Normalized::from_str(r#""eu-west""#, None).unwrap())
+///     .insert("replicas", Normalized::from_str("3", None).unwrap());
+/// let expr = Parsed::parse_str("{ region = region, replicas = replicas + 1 }")
+///     .unwrap()
+///     .resolve()
+///     .unwrap();
+/// let expr = env.typecheck(expr).unwrap().normalize();
+/// assert_eq!(expr.to_string(), r#"{ region = "eu-west", replicas = 4 }"#);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    bindings: Vec<(Label, Normalized)>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    /// Binds `name` to a typechecked value. Later bindings shadow earlier ones
+    /// of the same name.
+    pub fn insert(mut self, name: &str, value: Normalized) -> Self {
+        self.bindings.push((name.into(), value));
+        self
+    }
+
+    /// Typechecks an expression whose free variables are bound in this
+    /// environment
+    pub fn typecheck(&self, e: Resolved) -> Result<Typed, TypeError<X>> {
+        self.bind(e).typecheck()
+    }
+
+    /// Like `typecheck`, but also checks that the expression has type `ty`
+    pub fn typecheck_with(
+        &self,
+        e: Resolved,
+        ty: &Type,
+    ) -> Result<Typed, TypeError<X>> {
+        self.bind(e).typecheck_with(ty)
+    }
+
+    /// Wraps the expression in a `let` for each binding
+    fn bind(&self, e: Resolved) -> Resolved {
+        let e = self.bindings.iter().rev().fold(e.0, |e, (x, v)| {
+            rc(ExprF::Let(x.clone(), None, rc(ExprF::Embed(v.clone())), e))
+        });
+        Resolved(e)
+    }
+}
@@ -1,3 +1,6 @@
+pub use crate::diff::TypeDiff;
+pub use crate::imports::ImportError;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -7,6 +10,7 @@ pub enum Error {
     Decode(crate::binary::DecodeError),
     Resolve(crate::imports::ImportError),
     Typecheck(crate::typecheck::TypeError<dhall_core::X>),
+    Normalization(crate::normalize::NormalizationError),
     Deserialize(String),
 }
 
@@ -18,6 +22,7 @@ impl std::fmt::Display for Error {
             Error::Decode(err) => write!(f, "{:?}", err),
             Error::Resolve(err) => write!(f, "{:?}", err),
             Error::Typecheck(err) => write!(f, "{:?}", err),
+            Error::Normalization(err) => write!(f, "{}", err),
             Error::Deserialize(err) => write!(f, "{}", err),
         }
     }
@@ -49,3 +54,8 @@ impl From<crate::typecheck::TypeError<dhall_core::X>> for Error {
         Error::Typecheck(err)
     }
 }
+impl From<crate::normalize::NormalizationError> for Error {
+    fn from(err: crate::normalize::NormalizationError) -> Error {
+        Error::Normalization(err)
+    }
+}
@@ -20,13 +20,33 @@ macro_rules! derive_other_traits {
     };
 }
 
+// Source spans are ignored when comparing expressions
+macro_rules! derive_other_traits_noted {
+    ($ty:ident) => {
+        impl std::cmp::PartialEq for $ty {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.unnote() == other.0.unnote()
+            }
+        }
+
+        impl std::fmt::Display for $ty {
+            fn fmt(
+                &self,
+                f: &mut std::fmt::Formatter,
+            ) -> Result<(), std::fmt::Error> {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
 #[derive(Debug, Clone, Eq)]
-pub struct Parsed(pub(crate) SubExpr<X, Import>, pub(crate) ImportRoot);
-derive_other_traits!(Parsed);
+pub struct Parsed(pub(crate) SubExpr<Span, Import>, pub(crate) ImportRoot);
+derive_other_traits_noted!(Parsed);
 
 #[derive(Debug, Clone, Eq)]
-pub struct Resolved(pub(crate) SubExpr<X, Normalized>);
-derive_other_traits!(Resolved);
+pub struct Resolved(pub(crate) SubExpr<Span, Normalized>);
+derive_other_traits_noted!(Resolved);
 
 #[derive(Debug, Clone, Eq)]
 pub struct Typed(pub(crate) SubExpr<X, Normalized>, pub(crate) Option<Type>);
@@ -36,6 +56,13 @@ derive_other_traits!(Typed);
 pub struct Normalized(pub(crate) SubExpr<X, X>, pub(crate) Option<Type>);
 derive_other_traits!(Normalized);
 
+// Consistent with equality, which ignores the type
+impl std::hash::Hash for Normalized {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
 /// An expression of type `Type` (like `Bool` or `Natural -> Text`, but not `Type`)
 #[derive(Debug, Clone, Eq)]
 pub struct SimpleType(pub(crate) SubExpr<X, X>);
@@ -0,0 +1,75 @@
+//! Hash-consing of normalized expressions.
+//!
+//! Expressions built with `hashcons` are shared: two of them are structurally
+//! equal exactly when they are the same allocation. Normalization builds its
+//! output this way, which saves memory on repetitive expressions and lets
+//! equality checks compare pointers first.
+use dhall_core::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// An expression node, with subexpressions identified by their address.
+/// Since a node keeps its subexpressions alive, an address cannot be reused
+/// while a live node refers to it.
+type Key = ExprF<*const Expr<X, X>, Label, X, X>;
+
+#[derive(Default)]
+struct Table {
+    map: HashMap<Key, Weak<Expr<X, X>>>,
+    /// Size above which the table is cleared of dead entries
+    threshold: usize,
+}
+
+impl Table {
+    fn insert(&mut self, key: Key, e: &Rc<Expr<X, X>>) {
+        if self.map.len() >= self.threshold {
+            self.map.retain(|_, e| e.upgrade().is_some());
+            self.threshold = std::cmp::max(2 * self.map.len(), 1024);
+        }
+        self.map.insert(key, Rc::downgrade(e));
+    }
+}
+
+thread_local! {
+    static TABLE: RefCell<Table> = RefCell::new(Table::default());
+}
+
+/// Returns the shared copy of the given node. Its subexpressions must
+/// themselves have been built with `hashcons`.
+pub(crate) fn hashcons(e: Expr<X, X>) -> SubExpr<X, X> {
+    let key: Key = e.map_ref_simple(|e| &*e.0 as *const Expr<X, X>);
+    TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        if let Some(e) = table.map.get(&key).and_then(Weak::upgrade) {
+            return SubExpr(e);
+        }
+        let e = Rc::new(e);
+        table.insert(key, &e);
+        SubExpr(e)
+    })
+}
+
+/// Whether two expressions are the same allocation
+pub(crate) fn ptr_eq<S, T>(e1: &Expr<S, X>, e2: &Expr<T, X>) -> bool {
+    e1 as *const Expr<S, X> as *const () == e2 as *const Expr<T, X> as *const ()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashcons() {
+        let mk = || {
+            let one = hashcons(ExprF::NaturalLit(1u32.into()));
+            let two = hashcons(ExprF::NaturalLit(2u32.into()));
+            hashcons(ExprF::NEListLit(vec![one, two]))
+        };
+        let e1 = mk();
+        let e2 = mk();
+        assert!(ptr_eq(e1.as_ref(), e2.as_ref()));
+        let e3 = hashcons(ExprF::NaturalLit(3u32.into()));
+        assert!(!ptr_eq(e1.as_ref(), e3.as_ref()));
+    }
+}
@@ -1,15 +1,28 @@
+use crate::cache::{self, ContentHash};
 use crate::error::Error;
 use crate::expr::*;
 use dhall_core::*;
+use std::cell::RefCell;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
 
+/// An error while resolving an import, with the span of the import if known
 #[derive(Debug)]
 pub enum ImportError {
-    Recursive(Import, Box<Error>),
-    UnexpectedImport(Import),
+    Recursive(Import, Option<Span>, Box<Error>),
+    UnexpectedImport(Import, Option<Span>),
+}
+
+impl ImportError {
+    /// Where the import that failed appears in the source
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            ImportError::Recursive(_, span, _) => span.as_ref(),
+            ImportError::UnexpectedImport(_, span) => span.as_ref(),
+        }
+    }
 }
 
 /// A root from which to resolve relative imports.
@@ -20,8 +33,9 @@ pub enum ImportRoot {
 
 fn resolve_import(
     import: &Import,
+    span: Option<&Span>,
     root: &ImportRoot,
-) -> Result<Normalized, ImportError> {
+) -> Result<(Normalized, ContentHash), ImportError> {
     use self::ImportRoot::*;
     use dhall_core::FilePrefix::*;
     use dhall_core::ImportLocation::*;
@@ -37,38 +51,95 @@ fn resolve_import(
                 _ => unimplemented!("{:?}", import),
             };
             Ok(load_import(&path).map_err(|e| {
-                ImportError::Recursive(import.clone(), Box::new(e))
+                ImportError::Recursive(
+                    import.clone(),
+                    span.cloned(),
+                    Box::new(e),
+                )
             })?)
         }
         _ => unimplemented!("{:?}", import),
     }
 }
 
-fn load_import(f: &Path) -> Result<Normalized, Error> {
-    Ok(Parsed::parse_file(f)?.resolve()?.typecheck()?.normalize())
+/// Loads the file at `f`, along with the key of its typechecking result in
+/// the cache. The file is only typechecked if the cache has no result for it.
+fn load_import(f: &Path) -> Result<(Normalized, ContentHash), Error> {
+    let mut source = String::new();
+    File::open(f)?.read_to_string(&mut source)?;
+    let imports = RefCell::new(Vec::new());
+    let resolved =
+        resolve_expr(Parsed::parse_source(&source, f)?, Some(&imports))?;
+    let key = ContentHash::new(&source, &imports.into_inner());
+    let typed = match cache::lookup(key) {
+        Some(typed) => typed,
+        None => {
+            let typed = resolved.typecheck()?;
+            cache::insert(key, &typed);
+            typed
+        }
+    };
+    Ok((typed.normalize(), key))
 }
 
+/// Resolves the imports of `expr`, or fails on the first one if `imports` is
+/// `None`. Otherwise the cache keys of the imports are pushed to `imports`.
 fn resolve_expr(
     Parsed(expr, root): Parsed,
-    allow_imports: bool,
+    imports: Option<&RefCell<Vec<ContentHash>>>,
 ) -> Result<Resolved, ImportError> {
-    let resolve = |import: &Import| -> Result<Normalized, ImportError> {
-        if allow_imports {
-            let expr = resolve_import(import, &root)?;
-            Ok(expr)
-        } else {
-            Err(ImportError::UnexpectedImport(import.clone()))
+    let resolve = |import: &Import,
+                   span: Option<&Span>|
+     -> Result<Normalized, ImportError> {
+        match imports {
+            Some(imports) => {
+                let (expr, key) = resolve_import(import, span, &root)?;
+                imports.borrow_mut().push(key);
+                Ok(expr)
+            }
+            None => Err(ImportError::UnexpectedImport(
+                import.clone(),
+                span.cloned(),
+            )),
         }
     };
-    let expr = expr.as_ref().traverse_embed(&resolve)?;
-    Ok(Resolved(rc(expr)))
+    Ok(Resolved(resolve_embeds(&expr, None, &resolve)?))
+}
+
+/// Like `traverse_embed`, but also passes to `f` the span of the innermost
+/// note around each import
+fn resolve_embeds<F>(
+    e: &SubExpr<Span, Import>,
+    span: Option<&Span>,
+    f: &F,
+) -> Result<SubExpr<Span, Normalized>, ImportError>
+where
+    F: Fn(&Import, Option<&Span>) -> Result<Normalized, ImportError>,
+{
+    let span = match e.as_ref() {
+        ExprF::Note(span, _) => Some(span),
+        _ => span,
+    };
+    let e = e.as_ref().as_ref().traverse(
+        |e| resolve_embeds(e, span, f),
+        |_, e| resolve_embeds(e, span, f),
+        |span| Ok(span.clone()),
+        |import| f(import, span),
+        |l| Ok(l.clone()),
+    )?;
+    Ok(rc(e))
 }
 
 impl Parsed {
     pub fn parse_file(f: &Path) -> Result<Parsed, Error> {
         let mut buffer = String::new();
         File::open(f)?.read_to_string(&mut buffer)?;
-        let expr = parse_expr(&*buffer)?;
+        Parsed::parse_source(&buffer, f)
+    }
+
+    /// Parses `s` as the contents of the file `f`
+    fn parse_source(s: &str, f: &Path) -> Result<Parsed, Error> {
+        let expr = parse_expr_in_file(s, Some(f))?;
         let root = ImportRoot::LocalDir(f.parent().unwrap().to_owned());
         Ok(Parsed(expr, root))
     }
@@ -82,15 +153,15 @@ impl Parsed {
     pub fn parse_binary_file(f: &Path) -> Result<Parsed, Error> {
         let mut buffer = Vec::new();
         File::open(f)?.read_to_end(&mut buffer)?;
-        let expr = crate::binary::decode(&buffer)?;
+        let expr = crate::binary::decode(&buffer)?.note_absurd();
         let root = ImportRoot::LocalDir(f.parent().unwrap().to_owned());
         Ok(Parsed(expr, root))
     }
 
     pub fn resolve(self) -> Result<Resolved, ImportError> {
-        crate::imports::resolve_expr(self, true)
+        crate::imports::resolve_expr(self, Some(&RefCell::new(Vec::new())))
     }
     pub fn skip_resolve(self) -> Result<Resolved, ImportError> {
-        crate::imports::resolve_expr(self, false)
+        crate::imports::resolve_expr(self, None)
     }
 }
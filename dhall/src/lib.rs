@@ -122,10 +122,17 @@ mod tests;
 mod parser;
 
 mod binary;
+mod cache;
+mod diff;
+mod environment;
+mod hashcons;
 mod imports;
 mod normalize;
 mod traits;
 mod typecheck;
+pub use crate::cache::clear_typecheck_cache;
+pub use crate::environment::Environment;
+pub use crate::normalize::{NormalizationError, NormalizationLimit, TraceStep};
 pub use crate::traits::{
     Deserialize, DynamicType, SimpleStaticType, StaticType,
 };
@@ -141,6 +148,16 @@ pub fn from_str<'a, T: Deserialize<'a>>(
     T::from_str(s, ty)
 }
 
+/// Like `from_str`, but typechecking and normalization give up with an error
+/// when they reach `limit`
+pub fn from_str_with_limit<'a, T: Deserialize<'a>>(
+    s: &'a str,
+    ty: Option<&crate::expr::Type>,
+    limit: NormalizationLimit,
+) -> crate::error::Result<T> {
+    T::from_str_with_limit(s, ty, limit)
+}
+
 pub fn from_str_auto_type<'a, T: Deserialize<'a> + StaticType>(
     s: &'a str,
 ) -> crate::error::Result<T> {
@@ -1,12 +1,15 @@
-use std::error::Error;
+use dhall::error::Error;
+use dhall_core::Span;
+use std::error::Error as _;
 use std::io::{self, Read};
 use term_painter::ToStyle;
 
 const ERROR_STYLE: term_painter::Color = term_painter::Color::Red;
 const BOLD: term_painter::Attr = term_painter::Attr::Bold;
 
-fn print_error(message: &str, source: &str, start: usize, end: usize) {
-    let line_number = bytecount::count(source[..start].as_bytes(), b'\n');
+fn print_error(message: &str, span: &Span, source: &str) {
+    let (start, end) = (span.start, span.end);
+    let (line_number, column) = span.line_col(source);
     let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
     let line_end = source[end..].find('\n').unwrap_or(0) + end;
     let context_prefix = &source[line_start..start];
@@ -25,7 +28,7 @@ fn print_error(message: &str, source: &str, start: usize, end: usize) {
     BOLD.with(|| {
         print!("  -->");
     });
-    println!(" (stdin):{}:0", line_number);
+    println!(" {}:{}:{}", span.file_name(), line_number, column);
     BOLD.with(|| {
         println!("{:w$} |", "", w = line_number_width);
         print!("{} |", line_number_str);
@@ -44,13 +47,88 @@ fn print_error(message: &str, source: &str, start: usize, end: usize) {
                 " {:so$}{:^>ew$}",
                 "",
                 "",
-                so = source[line_start..start].chars().count(),
+                so = column - 1,
                 ew = ::std::cmp::max(1, source[start..end].chars().count())
             );
         });
     });
 }
 
+/// Prints an error message pointing at a span, reading the source back from
+/// disk if the span comes from an imported file
+fn print_span_error(message: &str, span: &Span, stdin: &str) {
+    match &span.file {
+        None => print_error(message, span, stdin),
+        Some(file) => match std::fs::read_to_string(&**file) {
+            Ok(source) => print_error(message, span, &source),
+            Err(_) => {
+                ERROR_STYLE.with(|| print!("Error: "));
+                println!("{} at {}", message, span);
+            }
+        },
+    }
+}
+
+fn print_failure(err: &Error, stdin: &str) {
+    match err {
+        Error::Parse(e) => {
+            let message = format!("Parse error: {}", e.message);
+            print_span_error(&message, &e.span, stdin);
+        }
+        Error::Resolve(e) => {
+            let message = match e {
+                dhall::error::ImportError::Recursive(import, _, _) => {
+                    format!("Error resolving import {}", import)
+                }
+                dhall::error::ImportError::UnexpectedImport(import, _) => {
+                    format!("Unexpected import {}", import)
+                }
+            };
+            match e.span() {
+                Some(span) => print_span_error(&message, span, stdin),
+                None => {
+                    ERROR_STYLE.with(|| print!("Error: "));
+                    println!("{}", message);
+                }
+            }
+            if let dhall::error::ImportError::Recursive(_, _, inner) = e {
+                print_failure(inner, stdin);
+            }
+        }
+        Error::Typecheck(e) => {
+            let explain = ::std::env::args().any(|s| s == "--explain");
+            if !explain {
+                term_painter::Color::BrightBlack.with(|| {
+                    println!("Use \"dhall --explain\" for detailed errors");
+                });
+            }
+            let message = e.type_message.description();
+            match &e.span {
+                Some(span) => print_span_error(message, span, stdin),
+                None => {
+                    ERROR_STYLE.with(|| print!("Error: "));
+                    println!("{}", message);
+                }
+            }
+            if explain {
+                println!("{}", e.type_message);
+            } else {
+                if let Some(hint) = e.type_message.suggestion() {
+                    println!("{}", hint);
+                }
+                for d in e.type_message.type_diff() {
+                    println!("{}", d);
+                }
+            }
+            println!("{}", e.current);
+        }
+        e => {
+            ERROR_STYLE.with(|| print!("Error: "));
+            println!("{}", e);
+        }
+    }
+}
+
 fn main() {
     let mut buffer = String::new();
     io::stdin().read_to_string(&mut buffer).unwrap();
@@ -58,34 +136,58 @@ fn main() {
     let expr = match dhall::expr::Parsed::parse_str(&buffer) {
         Ok(expr) => expr,
         Err(e) => {
-            print_error(&format!("Parse error {}", e), &buffer, 0, 0);
+            print_failure(&e, &buffer);
             return;
         }
     };
 
-    let expr = expr.resolve().unwrap();
+    let expr = match expr.resolve() {
+        Ok(expr) => expr,
+        Err(e) => {
+            print_failure(&e.into(), &buffer);
+            return;
+        }
+    };
 
     let expr = match expr.typecheck() {
         Ok(expr) => expr,
         Err(e) => {
-            let explain = ::std::env::args().any(|s| s == "--explain");
-            if !explain {
-                term_painter::Color::BrightBlack.with(|| {
-                    println!("Use \"dhall --explain\" for detailed errors");
-                });
-            }
-            ERROR_STYLE.with(|| print!("Error: "));
-            println!("{}", e.type_message.description());
-            if explain {
-                println!("{}", e.type_message);
-            }
-            println!("{}", e.current);
-            // FIXME Print source position
+            print_failure(&e.into(), &buffer);
             return;
         }
     };
 
-    let expr = expr.normalize();
+    // `--trace` prints every rewrite performed during normalization;
+    // `--trace=a.b` restricts it to the field `b` of the field `a`.
+    let trace = ::std::env::args().find_map(|s| {
+        if s == "--trace" {
+            Some(String::new())
+        } else if s.starts_with("--trace=") {
+            Some(s["--trace=".len()..].to_owned())
+        } else {
+            None
+        }
+    });
+    let expr = match trace {
+        None => expr.normalize(),
+        Some(path) => {
+            let path: Vec<&str> =
+                path.split('.').filter(|l| !l.is_empty()).collect();
+            let expr = match expr.select(&path) {
+                Some(expr) => expr,
+                None => {
+                    ERROR_STYLE.with(|| print!("Error: "));
+                    println!("No subexpression at {}", path.join("."));
+                    return;
+                }
+            };
+            let (expr, trace) = expr.normalize_traced();
+            for step in trace {
+                println!("{}", step);
+            }
+            expr
+        }
+    };
 
     println!("{}", expr);
 }
@@ -1,12 +1,17 @@
 use crate::error::{Error, Result};
 use crate::expr::{Normalized, Type};
+use crate::normalize::NormalizationLimit;
 use crate::traits::Deserialize;
 use dhall_core::*;
 use std::borrow::Cow;
 
 impl<'a, T: serde::Deserialize<'a>> Deserialize<'a> for T {
-    fn from_str(s: &'a str, ty: Option<&Type>) -> Result<Self> {
-        let expr = Normalized::from_str(s, ty)?;
+    fn from_str_with_limit(
+        s: &'a str,
+        ty: Option<&Type>,
+        limit: NormalizationLimit,
+    ) -> Result<Self> {
+        let expr = Normalized::from_str_with_limit(s, ty, limit)?;
         T::deserialize(Deserializer(Cow::Owned(expr.0)))
     }
 }
@@ -35,22 +40,24 @@ impl<'de: 'a, 'a> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        use std::convert::TryInto;
+        use num_traits::ToPrimitive;
         use ExprF::*;
         match self.0.as_ref().as_ref() {
-            NaturalLit(n) => match (*n).try_into() {
-                Ok(n64) => visitor.visit_u64(n64),
-                Err(_) => match (*n).try_into() {
-                    Ok(n32) => visitor.visit_u32(n32),
-                    Err(_) => unimplemented!(),
-                },
+            // Values that fit in 64 bits but not in the target type are
+            // rejected by the visitor itself.
+            NaturalLit(n) => match n.to_u64() {
+                Some(n) => visitor.visit_u64(n),
+                None => Err(serde::de::Error::custom(format!(
+                    "Natural {} does not fit in 64 bits",
+                    n
+                ))),
             },
-            IntegerLit(n) => match (*n).try_into() {
-                Ok(n64) => visitor.visit_i64(n64),
-                Err(_) => match (*n).try_into() {
-                    Ok(n32) => visitor.visit_i32(n32),
-                    Err(_) => unimplemented!(),
-                },
+            IntegerLit(n) => match n.to_i64() {
+                Some(n) => visitor.visit_i64(n),
+                None => Err(serde::de::Error::custom(format!(
+                    "Integer {} does not fit in 64 bits",
+                    n
+                ))),
             },
             RecordLit(m) => visitor.visit_map(
                 serde::de::value::MapDeserializer::new(m.iter().map(
@@ -67,3 +74,27 @@ impl<'de: 'a, 'a> serde::Deserializer<'de> for Deserializer<'a> {
         tuple_struct map struct enum identifier ignored_any
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_out_of_range() {
+        let n: u64 = crate::from_str("18446744073709551615", None).unwrap();
+        assert_eq!(n, u64::max_value());
+        let err =
+            crate::from_str::<u64>("18446744073709551616", None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Natural 18446744073709551616 does not fit in 64 bits"
+        );
+
+        let n: i64 = crate::from_str("-9223372036854775808", None).unwrap();
+        assert_eq!(n, i64::min_value());
+        let err =
+            crate::from_str::<i64>("-9223372036854775809", None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Integer -9223372036854775809 does not fit in 64 bits"
+        );
+    }
+}
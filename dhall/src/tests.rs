@@ -24,16 +24,8 @@ macro_rules! make_spec_test {
         #[allow(non_snake_case)]
         fn $name() {
             use crate::tests::*;
-            // Many tests stack overflow in debug mode
-            std::thread::Builder::new()
-                .stack_size(4 * 1024 * 1024)
-                .spawn(|| {
-                    run_test($path, Feature::$type, Status::$status)
-                        .map_err(|e| println!("{}", e))
-                        .unwrap();
-                })
-                .unwrap()
-                .join()
+            run_test($path, Feature::$type, Status::$status)
+                .map_err(|e| println!("{}", e))
                 .unwrap();
         }
     };
@@ -48,6 +40,7 @@ use std::path::PathBuf;
 pub enum Feature {
     Parser,
     Normalization,
+    AlphaNormalization,
     Typecheck,
     TypeInference,
 }
@@ -76,6 +69,7 @@ pub fn run_test(
     let feature_prefix = match feature {
         Parser => "parser/",
         Normalization => "normalization/",
+        AlphaNormalization => "alpha-normalization/",
         Typecheck => "typecheck/",
         TypeInference => "type-inference/",
     };
@@ -127,6 +121,13 @@ pub fn run_test(
                     let expr = expr.skip_typecheck().normalize();
                     assert_eq_display!(expr, expected);
                 }
+                AlphaNormalization => {
+                    let expr = expr
+                        .skip_typecheck()
+                        .skip_normalize()
+                        .alpha_normalize();
+                    assert_eq_display!(expr, expected);
+                }
             }
         }
         Failure => {
@@ -139,7 +140,7 @@ pub fn run_test(
                         e => panic!("Expected parse error, got: {:?}", e),
                     }
                 }
-                Normalization => unreachable!(),
+                Normalization | AlphaNormalization => unreachable!(),
                 Typecheck | TypeInference => {
                     parse_file_str(&file_path)?
                         .skip_resolve()?
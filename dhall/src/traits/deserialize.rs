@@ -1,46 +1,74 @@
 use crate::error::*;
 use crate::expr::*;
+use crate::normalize::NormalizationLimit;
 
 pub trait Deserialize<'a>: Sized {
-    fn from_str(s: &'a str, ty: Option<&Type>) -> Result<Self>;
+    fn from_str(s: &'a str, ty: Option<&Type>) -> Result<Self> {
+        Self::from_str_with_limit(s, ty, NormalizationLimit::unlimited())
+    }
+    /// Like `from_str`, but typechecking and normalization give up with an
+    /// error when they reach `limit`
+    fn from_str_with_limit(
+        s: &'a str,
+        ty: Option<&Type>,
+        limit: NormalizationLimit,
+    ) -> Result<Self>;
 }
 
 impl<'a> Deserialize<'a> for Parsed {
     /// Simply parses the provided string. Ignores the
-    /// provided type.
-    fn from_str(s: &'a str, _: Option<&Type>) -> Result<Self> {
+    /// provided type and limit.
+    fn from_str_with_limit(
+        s: &'a str,
+        _: Option<&Type>,
+        _: NormalizationLimit,
+    ) -> Result<Self> {
         Ok(Parsed::parse_str(s)?)
     }
 }
 
 impl<'a> Deserialize<'a> for Resolved {
     /// Parses and resolves the provided string. Ignores the
-    /// provided type.
-    fn from_str(s: &'a str, ty: Option<&Type>) -> Result<Self> {
-        Ok(Parsed::from_str(s, ty)?.resolve()?)
+    /// provided type and limit.
+    fn from_str_with_limit(
+        s: &'a str,
+        ty: Option<&Type>,
+        limit: NormalizationLimit,
+    ) -> Result<Self> {
+        Ok(Parsed::from_str_with_limit(s, ty, limit)?.resolve()?)
     }
 }
 
 impl<'a> Deserialize<'a> for Typed {
     /// Parses, resolves and typechecks the provided string.
-    fn from_str(s: &'a str, ty: Option<&Type>) -> Result<Self> {
-        let resolved = Resolved::from_str(s, ty)?;
-        match ty {
-            None => Ok(resolved.typecheck()?),
-            Some(t) => Ok(resolved.typecheck_with(t)?),
-        }
+    fn from_str_with_limit(
+        s: &'a str,
+        ty: Option<&Type>,
+        limit: NormalizationLimit,
+    ) -> Result<Self> {
+        let resolved = Resolved::from_str_with_limit(s, ty, limit)?;
+        Ok(resolved.typecheck_with_limit(ty, limit)?)
     }
 }
 
 impl<'a> Deserialize<'a> for Normalized {
     /// Parses, resolves, typechecks and normalizes the provided string.
-    fn from_str(s: &'a str, ty: Option<&Type>) -> Result<Self> {
-        Ok(Typed::from_str(s, ty)?.normalize())
+    fn from_str_with_limit(
+        s: &'a str,
+        ty: Option<&Type>,
+        limit: NormalizationLimit,
+    ) -> Result<Self> {
+        let typed = Typed::from_str_with_limit(s, ty, limit)?;
+        Ok(typed.normalize_with_limit(limit)?)
     }
 }
 
 impl<'a> Deserialize<'a> for Type {
-    fn from_str(s: &'a str, ty: Option<&Type>) -> Result<Self> {
-        Ok(Normalized::from_str(s, ty)?.into_type())
+    fn from_str_with_limit(
+        s: &'a str,
+        ty: Option<&Type>,
+        limit: NormalizationLimit,
+    ) -> Result<Self> {
+        Ok(Normalized::from_str_with_limit(s, ty, limit)?.into_type())
     }
 }
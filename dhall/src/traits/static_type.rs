@@ -41,7 +41,7 @@ impl SimpleStaticType for bool {
     }
 }
 
-impl SimpleStaticType for Natural {
+impl SimpleStaticType for usize {
     fn get_simple_static_type() -> SimpleType {
         mktype(dhall_expr!(Natural))
     }
@@ -59,7 +59,7 @@ impl SimpleStaticType for u64 {
     }
 }
 
-impl SimpleStaticType for Integer {
+impl SimpleStaticType for isize {
     fn get_simple_static_type() -> SimpleType {
         mktype(dhall_expr!(Integer))
     }
@@ -0,0 +1,53 @@
+#![feature(test)]
+extern crate test;
+
+use dhall_core::{parse_expr, parse_expr_with_pest};
+use test::Bencher;
+
+/// A list of records exercising most of the syntax
+fn large_expression() -> String {
+    let item = |i| {
+        format!(
+            r#"{{ name = "item ${{Natural/show {i}}}", value = +{i}
+, weight = {i}.5, tags = [ "a", "b" ] : List Text
+, check = λ(x : Natural) → if Natural/even x then x * {i} else x + 1
+, kind = < Small : {{}} | Large : Natural >.Large {i}
+}}"#,
+            i = i
+        )
+    };
+    let items: Vec<_> = (0..200).map(item).collect();
+    format!(
+        "let f = λ(a : Type) → λ(xs : List a) → List/length a xs\nin  [ {} ]",
+        items.join("\n, ")
+    )
+}
+
+/// Nested parentheses, which the pest grammar reparses at each level
+fn nested_expression() -> String {
+    "(".repeat(12) + "x" + &")".repeat(12)
+}
+
+#[bench]
+fn bench_parse_large(b: &mut Bencher) {
+    let s = large_expression();
+    b.iter(|| parse_expr(&s).unwrap())
+}
+
+#[bench]
+fn bench_parse_large_with_pest(b: &mut Bencher) {
+    let s = large_expression();
+    b.iter(|| parse_expr_with_pest(&s).unwrap())
+}
+
+#[bench]
+fn bench_parse_nested(b: &mut Bencher) {
+    let s = nested_expression();
+    b.iter(|| parse_expr(&s).unwrap())
+}
+
+#[bench]
+fn bench_parse_nested_with_pest(b: &mut Bencher) {
+    let s = nested_expression();
+    b.iter(|| parse_expr_with_pest(&s).unwrap())
+}
@@ -1,6 +1,5 @@
-use std::cmp::Eq;
-use std::collections::HashMap;
-use std::hash::Hash;
+use std::cmp::{max, Ordering};
+use std::rc::Rc;
 
 /// A `(Context a)` associates `Text` labels with values of type `a`
 ///
@@ -14,13 +13,64 @@ use std::hash::Hash;
 /// have multiple ordered occurrences of the same key and you can query for the
 /// `n`th occurrence of a given key.
 ///
-#[derive(Debug, Clone)]
-pub struct Context<K: Eq + Hash, T>(HashMap<K, Vec<T>>);
+/// The entries are stored as a persistent stack, so that entering a binder
+/// shares the enclosing context instead of copying it. A persistent map from
+/// each key to its latest entry lets `lookup` and `count` skip the entries of
+/// other keys.
+#[derive(Debug)]
+pub struct Context<K, T> {
+    /// The most recently inserted entry
+    last: Option<Rc<Entry<K, T>>>,
+    /// The most recently inserted entry of each key
+    by_key: Map<K, Rc<Entry<K, T>>>,
+}
+
+#[derive(Debug)]
+struct Entry<K, T> {
+    key: K,
+    value: T,
+    /// The entry inserted before this one
+    prev: Option<Rc<Entry<K, T>>>,
+    /// The latest entry of the same key before this one
+    shadowed: Option<Rc<Entry<K, T>>>,
+    /// How many entries of the same key were inserted before this one
+    shadowed_count: usize,
+}
+
+impl<K, T> Clone for Context<K, T> {
+    fn clone(&self) -> Self {
+        Context {
+            last: self.last.clone(),
+            by_key: self.by_key.clone(),
+        }
+    }
+}
 
-impl<K: Hash + Eq + Clone, T> Context<K, T> {
+impl<K: Ord + Clone, T> Context<K, T> {
     /// An empty context with no key-value pairs
     pub fn new() -> Self {
-        Context(HashMap::new())
+        Context {
+            last: None,
+            by_key: Map(None),
+        }
+    }
+
+    /// Add a key-value pair to the `Context`
+    pub fn insert(&self, k: K, v: T) -> Self {
+        let shadowed = self.by_key.get(&k).cloned();
+        let entry = Rc::new(Entry {
+            key: k.clone(),
+            value: v,
+            prev: self.last.clone(),
+            shadowed_count: shadowed
+                .as_ref()
+                .map_or(0, |e| e.shadowed_count + 1),
+            shadowed,
+        });
+        Context {
+            by_key: self.by_key.insert(k, Rc::clone(&entry)),
+            last: Some(entry),
+        }
     }
 
     /// Look up a key by name and index
@@ -31,28 +81,213 @@ impl<K: Hash + Eq + Clone, T> Context<K, T> {
     /// lookup k n (insert k v c) = lookup k (n - 1) c  -- 1 <= n
     /// lookup k n (insert j v c) = lookup k  n      c  -- k /= j
     /// ```
+    ///
+    /// This only goes through the `n` entries of `k` that shadow the one
+    /// returned.
     pub fn lookup<'a>(&'a self, k: &K, n: usize) -> Option<&'a T> {
-        self.0.get(k).and_then(|v| v.get(v.len() - 1 - n))
+        let mut entry = self.by_key.get(k)?;
+        if n > entry.shadowed_count {
+            return None;
+        }
+        for _ in 0..n {
+            entry = entry.shadowed.as_ref()?;
+        }
+        Some(&entry.value)
+    }
+
+    /// Number of occurrences of a given key
+    pub fn count(&self, k: &K) -> usize {
+        self.by_key.get(k).map_or(0, |e| e.shadowed_count + 1)
     }
 
     pub fn map<U, F: Fn(&T) -> U>(&self, f: F) -> Context<K, U> {
-        Context(
-            self.0
-                .iter()
-                .map(|(k, v)| ((*k).clone(), v.iter().map(&f).collect()))
-                .collect(),
-        )
+        let entries: Vec<_> = self.iter().collect();
+        entries
+            .into_iter()
+            .rev()
+            .fold(Context::new(), |ctx, (k, v)| ctx.insert(k.clone(), f(v)))
+    }
+
+    /// The entries of the context, most recently inserted first
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &T)> {
+        let mut next = self.last.as_ref();
+        std::iter::from_fn(move || {
+            let entry = next?;
+            next = entry.prev.as_ref();
+            Some((&entry.key, &entry.value))
+        })
     }
 }
 
-impl<K: Hash + Eq + Clone, T: Clone> Context<K, T> {
-    /// Add a key-value pair to the `Context`
-    pub fn insert(&self, k: K, v: T) -> Self {
-        let mut ctx = (*self).clone();
-        {
-            let m = ctx.0.entry(k).or_insert_with(Vec::new);
-            m.push(v);
+impl<K, T> Drop for Entry<K, T> {
+    // Unlink the entries iteratively, to avoid overflowing the call stack
+    // when dropping a deep context
+    fn drop(&mut self) {
+        let mut pending: Vec<_> = self
+            .prev
+            .take()
+            .into_iter()
+            .chain(self.shadowed.take())
+            .collect();
+        while let Some(entry) = pending.pop() {
+            if let Ok(mut entry) = Rc::try_unwrap(entry) {
+                pending.extend(entry.prev.take());
+                pending.extend(entry.shadowed.take());
+            }
+        }
+    }
+}
+
+/// A persistent binary search tree, kept balanced like an AVL tree. Inserting
+/// copies only the path to the new node.
+#[derive(Debug)]
+struct Map<K, V>(Option<Rc<Node<K, V>>>);
+
+#[derive(Debug)]
+struct Node<K, V> {
+    key: K,
+    value: V,
+    height: usize,
+    left: Map<K, V>,
+    right: Map<K, V>,
+}
+
+impl<K, V> Clone for Map<K, V> {
+    fn clone(&self) -> Self {
+        Map(self.0.clone())
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Map<K, V> {
+    fn get(&self, k: &K) -> Option<&V> {
+        let mut map = self;
+        while let Some(node) = &map.0 {
+            map = match k.cmp(&node.key) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => return Some(&node.value),
+            };
+        }
+        None
+    }
+
+    /// Adds `k`, or replaces its value if it is already there
+    fn insert(&self, k: K, v: V) -> Self {
+        let node = match &self.0 {
+            None => return Map::node(k, v, Map(None), Map(None)),
+            Some(node) => node,
+        };
+        let (key, value) = (node.key.clone(), node.value.clone());
+        match k.cmp(&node.key) {
+            Ordering::Less => Map::balance(
+                key,
+                value,
+                node.left.insert(k, v),
+                node.right.clone(),
+            ),
+            Ordering::Greater => Map::balance(
+                key,
+                value,
+                node.left.clone(),
+                node.right.insert(k, v),
+            ),
+            Ordering::Equal => {
+                Map::node(k, v, node.left.clone(), node.right.clone())
+            }
+        }
+    }
+
+    fn height(&self) -> usize {
+        self.0.as_ref().map_or(0, |node| node.height)
+    }
+
+    fn node(key: K, value: V, left: Self, right: Self) -> Self {
+        let height = 1 + max(left.height(), right.height());
+        Map(Some(Rc::new(Node {
+            key,
+            value,
+            height,
+            left,
+            right,
+        })))
+    }
+
+    /// Like `node`, but rotates the tree back into balance when the heights
+    /// of the subtrees differ by two, as they may after an insertion
+    fn balance(key: K, value: V, left: Self, right: Self) -> Self {
+        let (hl, hr) = (left.height(), right.height());
+        if hl > hr + 1 {
+            let l = left.0.as_ref().unwrap();
+            if l.left.height() >= l.right.height() {
+                let right = Map::node(key, value, l.right.clone(), right);
+                Map::node(l.key.clone(), l.value.clone(), l.left.clone(), right)
+            } else {
+                let lr = l.right.0.as_ref().unwrap();
+                Map::node(
+                    lr.key.clone(),
+                    lr.value.clone(),
+                    Map::node(
+                        l.key.clone(),
+                        l.value.clone(),
+                        l.left.clone(),
+                        lr.left.clone(),
+                    ),
+                    Map::node(key, value, lr.right.clone(), right),
+                )
+            }
+        } else if hr > hl + 1 {
+            let r = right.0.as_ref().unwrap();
+            if r.right.height() >= r.left.height() {
+                let left = Map::node(key, value, left, r.left.clone());
+                Map::node(r.key.clone(), r.value.clone(), left, r.right.clone())
+            } else {
+                let rl = r.left.0.as_ref().unwrap();
+                Map::node(
+                    rl.key.clone(),
+                    rl.value.clone(),
+                    Map::node(key, value, left, rl.left.clone()),
+                    Map::node(
+                        r.key.clone(),
+                        r.value.clone(),
+                        rl.right.clone(),
+                        r.right.clone(),
+                    ),
+                )
+            }
+        } else {
+            Map::node(key, value, left, right)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+
+    #[test]
+    fn test_lookup() {
+        let ctx = Context::new().insert("x", 1).insert("y", 2).insert("x", 3);
+        assert_eq!(ctx.lookup(&"x", 0), Some(&3));
+        assert_eq!(ctx.lookup(&"x", 1), Some(&1));
+        assert_eq!(ctx.lookup(&"x", 2), None);
+        assert_eq!(ctx.lookup(&"y", 0), Some(&2));
+        assert_eq!(ctx.count(&"x"), 2);
+        let ctx = ctx.map(|v| v * 10);
+        assert_eq!(ctx.lookup(&"x", 1), Some(&10));
+    }
+
+    #[test]
+    fn test_deep() {
+        let n = 100_000;
+        let mut ctx = Context::new();
+        for i in 0..n {
+            ctx = ctx.insert(i % 100, i).insert(100, i);
         }
-        ctx
+        assert_eq!(ctx.count(&7), n / 100);
+        assert_eq!(ctx.lookup(&7, 0), Some(&(n - 100 + 7)));
+        assert_eq!(ctx.lookup(&7, 2), Some(&(n - 300 + 7)));
+        assert_eq!(ctx.lookup(&100, 0), Some(&(n - 1)));
+        assert_eq!(ctx.lookup(&101, 0), None);
+        assert_eq!(ctx.iter().count(), 2 * n);
     }
 }
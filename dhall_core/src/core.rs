@@ -1,14 +1,15 @@
 #![allow(non_snake_case)]
 use crate::*;
+use num_bigint::{BigInt, BigUint};
 use std::collections::BTreeMap;
 use std::rc::Rc;
 
-pub type Integer = isize;
-pub type Natural = usize;
+pub type Integer = BigInt;
+pub type Natural = BigUint;
 pub type Double = NaiveDouble;
 
 /// An empty type
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum X {}
 
 pub fn trivial_result<T>(x: Result<T, X>) -> T {
@@ -30,6 +31,12 @@ impl PartialEq for NaiveDouble {
 
 impl Eq for NaiveDouble {}
 
+impl std::hash::Hash for NaiveDouble {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
 impl From<f64> for NaiveDouble {
     fn from(x: f64) -> Self {
         NaiveDouble(x)
@@ -63,7 +70,7 @@ impl From<NaiveDouble> for f64 {
 /// Note that Dhall does not support functions from terms to types and therefore
 /// Dhall is not a dependently typed language
 ///
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Const {
     Type,
     Kind,
@@ -106,12 +113,12 @@ pub enum Const {
 /// Zero indices are omitted when pretty-printing `Var`s and non-zero indices
 /// appear as a numeric suffix.
 ///
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct V<Label>(pub Label, pub usize);
 
 // Definition order must match precedence order for
 // pretty-printing to work correctly
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum BinOp {
     /// x ? y
     ImportAlt,
@@ -140,7 +147,7 @@ pub enum BinOp {
 }
 
 /// Built-ins
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Builtin {
     Bool,
     Natural,
@@ -176,7 +183,7 @@ pub type ParsedExpr = SubExpr<X, Import>;
 pub type ResolvedExpr = SubExpr<X, X>;
 pub type DhallExpr = ResolvedExpr;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub struct SubExpr<Note, Embed>(pub Rc<Expr<Note, Embed>>);
 
 pub type Expr<Note, Embed> = ExprF<SubExpr<Note, Embed>, Label, Note, Embed>;
@@ -185,7 +192,7 @@ pub type Expr<Note, Embed> = ExprF<SubExpr<Note, Embed>, Label, Note, Embed>;
 // Having the recursion out of the enum definition enables writing
 // much more generic code and improves pattern-matching behind
 // smart pointers.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ExprF<SubExpr, Label, Note, Embed> {
     ///  `Const c                                  ~  c`
     Const(Const),
@@ -370,8 +377,8 @@ impl<SE, L, N, E> ExprF<SE, L, N, E> {
             Const(k) => Const(*k),
             Builtin(v) => Builtin(*v),
             BoolLit(b) => BoolLit(*b),
-            NaturalLit(n) => NaturalLit(*n),
-            IntegerLit(n) => IntegerLit(*n),
+            NaturalLit(n) => NaturalLit(n.clone()),
+            IntegerLit(n) => IntegerLit(n.clone()),
             DoubleLit(n) => DoubleLit(*n),
             TextLit(t) => TextLit(t.as_ref()),
             BinOp(o, x, y) => BinOp(*o, x, y),
@@ -670,6 +677,35 @@ impl<N: Clone> SubExpr<N, X> {
     }
 }
 
+impl<N, E: Clone> SubExpr<N, E> {
+    /// Removes the notes of the expression, like its source spans
+    pub fn unnote(&self) -> SubExpr<X, E> {
+        match self.as_ref() {
+            ExprF::Note(_, e) => e.unnote(),
+            e => rc(e.map_ref(
+                |e| e.unnote(),
+                |_, e| e.unnote(),
+                |_| unreachable!(),
+                E::clone,
+                Label::clone,
+            )),
+        }
+    }
+}
+
+impl<E: Clone> SubExpr<X, E> {
+    /// When we know there is no Note
+    pub fn note_absurd<N>(&self) -> SubExpr<N, E> {
+        rc(self.as_ref().map_ref(
+            |e| e.note_absurd(),
+            |_, e| e.note_absurd(),
+            |_| unreachable!(),
+            E::clone,
+            Label::clone,
+        ))
+    }
+}
+
 impl<N: Clone> Expr<N, X> {
     // This is all very sad and I hope this can be avoided sometime
     pub fn absurd_rec<T>(&self) -> Expr<N, T> {
@@ -0,0 +1,285 @@
+//! A lossless concrete syntax tree, for formatters, refactoring and lint
+//! tools.
+//!
+//! Every byte of the source, including whitespace and comments, belongs to
+//! exactly one token, and the tokens are grouped into nodes that follow the
+//! noted expressions of the AST. Writing out the tokens in order gives back
+//! the source unchanged.
+use crate::*;
+use std::cell::RefCell;
+use std::fmt;
+
+/// What a token consists of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    /// A line comment or a block comment
+    Comment,
+    /// A keyword such as `let` or `merge`
+    Keyword,
+    /// An operator or punctuation
+    Symbol,
+    /// A label, builtin or variable name
+    Label,
+    /// Numbers, text contents, import locations and hashes
+    Literal,
+}
+
+/// A range of bytes of the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token {
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+
+    /// Whether the token is whitespace or a comment
+    pub fn is_trivia(&self) -> bool {
+        self.kind == TokenKind::Whitespace || self.kind == TokenKind::Comment
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CstElement {
+    Node(CstNode),
+    Token(Token),
+}
+
+/// The source of an expression: its subexpressions and the tokens between
+/// them
+#[derive(Debug, Clone)]
+pub struct CstNode {
+    start: usize,
+    end: usize,
+    expr: SubExpr<Span, Import>,
+    children: Vec<CstElement>,
+}
+
+impl CstNode {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn children(&self) -> &[CstElement] {
+        &self.children
+    }
+
+    /// The expression the node stands for
+    pub fn to_expr(&self) -> SubExpr<Span, Import> {
+        self.expr.clone()
+    }
+
+    /// All the tokens of the node, in order
+    pub fn tokens(&self) -> Vec<Token> {
+        let mut tokens = vec![];
+        self.push_tokens(&mut tokens);
+        tokens
+    }
+
+    fn push_tokens(&self, tokens: &mut Vec<Token>) {
+        for child in &self.children {
+            match child {
+                CstElement::Node(node) => node.push_tokens(tokens),
+                CstElement::Token(token) => tokens.push(*token),
+            }
+        }
+    }
+
+    fn child_at(&self, offset: usize) -> Option<&CstElement> {
+        self.children.iter().find(|child| {
+            let (start, end) = match child {
+                CstElement::Node(node) => (node.start, node.end),
+                CstElement::Token(token) => (token.start, token.end),
+            };
+            start <= offset && offset < end
+        })
+    }
+}
+
+/// The concrete syntax tree of a source string
+#[derive(Debug, Clone)]
+pub struct Cst<'a> {
+    source: &'a str,
+    root: CstNode,
+}
+
+impl<'a> Cst<'a> {
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// The node of the whole source, including the leading and trailing
+    /// whitespace
+    pub fn root(&self) -> &CstNode {
+        &self.root
+    }
+
+    /// The innermost node that contains the given byte offset
+    pub fn node_at(&self, offset: usize) -> Option<&CstNode> {
+        if offset >= self.root.end {
+            return None;
+        }
+        let mut node = &self.root;
+        while let Some(CstElement::Node(child)) = node.child_at(offset) {
+            node = child;
+        }
+        Some(node)
+    }
+
+    /// The token that contains the given byte offset
+    pub fn token_at(&self, offset: usize) -> Option<Token> {
+        let mut node = &self.root;
+        loop {
+            match node.child_at(offset)? {
+                CstElement::Node(child) => node = child,
+                CstElement::Token(token) => return Some(*token),
+            }
+        }
+    }
+
+    pub fn tokens(&self) -> Vec<Token> {
+        self.root.tokens()
+    }
+
+    /// The AST of the whole source
+    pub fn to_expr(&self) -> SubExpr<Span, Import> {
+        self.root.to_expr()
+    }
+}
+
+impl<'a> fmt::Display for Cst<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.tokens() {
+            f.write_str(token.text(self.source))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_cst(s: &str) -> ParseResult<Cst> {
+    let (expr, tokens) = crate::descent::parse_with_tokens(s)?;
+    let mut tokens = tokens.into_iter().peekable();
+    let root = build_node(expr, 0, s.len(), &mut tokens);
+    Ok(Cst { source: s, root })
+}
+
+fn build_node(
+    expr: SubExpr<Span, Import>,
+    start: usize,
+    end: usize,
+    tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
+) -> CstNode {
+    let mut children = noted_children(&expr).into_iter().peekable();
+    let mut elements: Vec<CstElement> = vec![];
+    while let Some(token) = tokens.peek().cloned() {
+        if token.start >= end {
+            break;
+        }
+        // Skip subexpressions that overlap the tokens seen so far
+        while children.peek().map_or(false, |(span, _)| {
+            span.start < token.start || span.end <= span.start
+        }) {
+            children.next();
+        }
+        if let Some((span, _)) = children.peek() {
+            if span.start == token.start {
+                let (span, child) = children.next().unwrap();
+                let node = build_node(child, span.start, span.end, tokens);
+                elements.push(CstElement::Node(node));
+                continue;
+            }
+        }
+        tokens.next();
+        match elements.last_mut() {
+            Some(CstElement::Token(last))
+                if last.kind == token.kind
+                    && last.end == token.start
+                    && (token.kind == TokenKind::Whitespace
+                        || token.kind == TokenKind::Literal) =>
+            {
+                last.end = token.end
+            }
+            _ => elements.push(CstElement::Token(token)),
+        }
+    }
+    CstNode {
+        start,
+        end,
+        expr,
+        children: elements,
+    }
+}
+
+/// The outermost noted subexpressions of an expression, in source order
+fn noted_children(
+    expr: &SubExpr<Span, Import>,
+) -> Vec<(Span, SubExpr<Span, Import>)> {
+    let mut e = expr;
+    while let ExprF::Note(_, inner) = e.as_ref() {
+        e = inner;
+    }
+    let children = RefCell::new(vec![]);
+    push_noted_children(e, &children);
+    let mut children = children.into_inner();
+    children.sort_by_key(|child| child.0.start);
+    children
+}
+
+fn push_noted_children(
+    e: &SubExpr<Span, Import>,
+    children: &RefCell<Vec<(Span, SubExpr<Span, Import>)>>,
+) {
+    e.as_ref().map_ref_simple(|child| match child.as_ref() {
+        ExprF::Note(span, _) => {
+            children.borrow_mut().push((span.clone(), child.clone()))
+        }
+        _ => push_noted_children(child, children),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cst() {
+        let sources = [
+            "  1 ",
+            "-- comment\n{ x = 1, `y z` = [ True ] }  {- block -}\n",
+            "let x = 1\nlet y = x + 2 -- two\nin  \\(z : Natural) -> y",
+            "''\n  foo ${ \"bar\\n\" }\n  baz''",
+            concat!(
+                "./foo/bar sha256:",
+                "00000000000000000000000000000000",
+                "00000000000000000000000000000000",
+                " as Text",
+            ),
+            "merge { A = \\(x : Natural) -> x } < A = 1 | B : Bool > : Natural",
+        ];
+        for s in sources.iter() {
+            let cst = parse_cst(s).unwrap();
+            assert_eq!(cst.to_string(), *s);
+            assert_eq!(cst.to_expr().unnote(), parse_expr(s).unwrap().unnote());
+        }
+
+        let s = "{ x = [ 1, +2 ] } -- rest";
+        let cst = parse_cst(s).unwrap();
+        let node = cst.node_at(s.find('+').unwrap()).unwrap();
+        assert_eq!(&s[node.start()..node.end()], "+2");
+        assert_eq!(node.to_expr().unnote(), parse_expr("+2").unwrap().unnote());
+        let token = cst.token_at(s.len() - 1).unwrap();
+        assert_eq!(token.kind, TokenKind::Comment);
+        assert_eq!(token.text(s), "-- rest");
+        assert_eq!(cst.node_at(0).unwrap().start(), 0);
+        assert!(cst.node_at(s.len()).is_none());
+    }
+}
@@ -0,0 +1,1435 @@
+// A hand-written recursive-descent parser for the grammar in
+// dhall_parser/src/dhall.abnf. It accepts the same language as the pest
+// parser and builds the same AST, but avoids the pest parse tree and the
+// exponential re-parsing that the PEG alternatives of `expression` cause:
+// shared prefixes of alternatives are parsed once and then inspected.
+//
+// Each method follows the grammar rule of the same name. Methods return
+// `Failure::Backtrack` when the input does not match, after which the caller
+// may try another alternative from a saved position (see `attempt`), as PEG
+// ordered choice does.
+//
+// All input is consumed through `advance`, which can also record it as
+// tokens for the concrete syntax tree in cst.rs.
+
+use num_traits::ToPrimitive;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::parser::{
+    is_builtin, ParseError, ParseResult, ParsedExpr, ParsedSubExpr, ParsedText,
+    ParsedTextContents,
+};
+use crate::*;
+
+use crate::ExprF::*;
+
+enum Failure {
+    /// The input does not match the rule
+    Backtrack,
+    /// The input matches but is invalid, e.g. a literal that overflows
+    Invalid(usize, usize, String),
+}
+
+type Step<T> = Result<T, Failure>;
+
+enum Selector {
+    Field(Label),
+    Projection(Vec<Label>),
+}
+
+enum UnionAlternative {
+    Type(Label, ParsedSubExpr),
+    Value(Label, ParsedSubExpr, Vec<(Label, ParsedSubExpr)>),
+}
+
+/// Operators from the loosest to the tightest binding, and whether they must
+/// be followed by whitespace
+const OPERATORS: &[(&[&str], bool, crate::BinOp)] = &[
+    (&["?"], true, crate::BinOp::ImportAlt),
+    (&["||"], false, crate::BinOp::BoolOr),
+    (&["+"], true, crate::BinOp::NaturalPlus),
+    (&["++"], false, crate::BinOp::TextAppend),
+    (&["#"], false, crate::BinOp::ListAppend),
+    (&["&&"], false, crate::BinOp::BoolAnd),
+    (&["∧", "/\\"], false, crate::BinOp::Combine),
+    (&["⫽", "//"], false, crate::BinOp::Prefer),
+    (&["⩓", "//\\\\"], false, crate::BinOp::CombineTypes),
+    (&["*"], false, crate::BinOp::NaturalTimes),
+    (&["=="], false, crate::BinOp::BoolEQ),
+    (&["!="], false, crate::BinOp::BoolNE),
+];
+
+const KEYWORDS: &[&str] = &[
+    "if", "then", "else", "let", "in", "using", "missing", "as", "Infinity",
+    "NaN", "merge", "Some",
+];
+
+pub(crate) fn parse(
+    input: &str,
+    file: Option<Rc<PathBuf>>,
+) -> ParseResult<ParsedSubExpr> {
+    Ok(run(input, file, None)?.0)
+}
+
+/// Parses the input and returns the tokens that make it up, in order
+pub(crate) fn parse_with_tokens(
+    input: &str,
+) -> ParseResult<(ParsedSubExpr, Vec<Token>)> {
+    let (e, tokens) = run(input, None, Some(vec![]))?;
+    Ok((e, tokens.unwrap()))
+}
+
+fn run(
+    input: &str,
+    file: Option<Rc<PathBuf>>,
+    tokens: Option<Vec<Token>>,
+) -> ParseResult<(ParsedSubExpr, Option<Vec<Token>>)> {
+    let mut parser = Parser {
+        input,
+        pos: 0,
+        file,
+        farthest: 0,
+        expected: vec![],
+        tokens,
+    };
+    match parser.final_expression() {
+        Ok(e) => Ok((e, parser.tokens)),
+        Err(Failure::Backtrack) => Err(ParseError {
+            message: format!("expected {}", parser.expected.join(" or ")),
+            span: parser.span_at(parser.farthest, parser.farthest),
+        }),
+        Err(Failure::Invalid(start, end, message)) => Err(ParseError {
+            message,
+            span: parser.span_at(start, end),
+        }),
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    file: Option<Rc<PathBuf>>,
+    /// The farthest position where the input failed to match, and what was
+    /// expected there
+    farthest: usize,
+    expected: Vec<&'static str>,
+    /// The input consumed so far, when building a concrete syntax tree
+    tokens: Option<Vec<Token>>,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn fail<T>(&mut self, expected: &'static str) -> Step<T> {
+        if self.pos > self.farthest {
+            self.farthest = self.pos;
+            self.expected.clear();
+        }
+        if self.pos == self.farthest && !self.expected.contains(&expected) {
+            self.expected.push(expected);
+        }
+        Err(Failure::Backtrack)
+    }
+
+    fn invalid<T>(&self, start: usize, message: String) -> Step<T> {
+        Err(Failure::Invalid(start, self.pos, message))
+    }
+
+    /// Runs `f`, rewinding the input if it does not match
+    fn attempt<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Step<T>,
+    ) -> Step<Option<T>> {
+        let pos = self.pos;
+        let mark = self.token_mark();
+        match f(self) {
+            Ok(x) => Ok(Some(x)),
+            Err(Failure::Backtrack) => {
+                self.pos = pos;
+                if let Some(tokens) = &mut self.tokens {
+                    tokens.truncate(mark);
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn advance(&mut self, len: usize, kind: TokenKind) {
+        let start = self.pos;
+        self.pos += len;
+        if let Some(tokens) = &mut self.tokens {
+            tokens.push(Token {
+                kind,
+                start,
+                end: self.pos,
+            });
+        }
+    }
+
+    fn token_mark(&self) -> usize {
+        self.tokens.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Replaces the tokens recorded since `mark` with a single one that
+    /// starts at `start`
+    fn join_tokens(&mut self, mark: usize, start: usize, kind: TokenKind) {
+        let end = self.pos;
+        if let Some(tokens) = &mut self.tokens {
+            tokens.truncate(mark);
+            tokens.push(Token { kind, start, end });
+        }
+    }
+
+    fn eat(&mut self, s: &'static str) -> bool {
+        if self.rest().starts_with(s) {
+            let kind = if s.starts_with(char::is_alphabetic) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Symbol
+            };
+            self.advance(s.len(), kind);
+            true
+        } else {
+            let _ = self.fail::<()>(s);
+            false
+        }
+    }
+
+    fn token(&mut self, s: &'static str) -> Step<()> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(Failure::Backtrack)
+        }
+    }
+
+    /// The characters at the current position that satisfy `f`
+    fn peek_while(&self, f: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c: char| !f(c)).unwrap_or_else(|| rest.len());
+        &rest[..len]
+    }
+
+    /// Consumes characters while they satisfy `f` and returns them
+    fn take_while(
+        &mut self,
+        kind: TokenKind,
+        f: impl Fn(char) -> bool,
+    ) -> &'a str {
+        let s = self.peek_while(f);
+        if !s.is_empty() {
+            self.advance(s.len(), kind);
+        }
+        s
+    }
+
+    fn take_while1(
+        &mut self,
+        expected: &'static str,
+        kind: TokenKind,
+        f: impl Fn(char) -> bool,
+    ) -> Step<&'a str> {
+        match self.take_while(kind, f) {
+            "" => self.fail(expected),
+            s => Ok(s),
+        }
+    }
+
+    fn span(&self, start: usize) -> Span {
+        self.span_at(start, self.pos)
+    }
+
+    fn span_at(&self, start: usize, end: usize) -> Span {
+        Span {
+            file: self.file.clone(),
+            start,
+            end,
+        }
+    }
+
+    fn noted(&self, start: usize, e: ParsedExpr) -> ParsedSubExpr {
+        rc(Note(self.span(start), rc(e)))
+    }
+
+    fn final_expression(&mut self) -> Step<ParsedSubExpr> {
+        self.whsp();
+        let e = self.expression()?;
+        self.whsp();
+        if self.pos < self.input.len() {
+            return self.fail("end of input");
+        }
+        Ok(e)
+    }
+
+    fn whsp(&mut self) {
+        while let Some(len) = whitespace_chunk(self.rest()) {
+            let rest = self.rest();
+            let kind = if rest.starts_with("--") || rest.starts_with("{-") {
+                TokenKind::Comment
+            } else {
+                TokenKind::Whitespace
+            };
+            self.advance(len, kind);
+        }
+    }
+
+    fn whsp1(&mut self) -> Step<()> {
+        if whitespace_chunk(self.rest()).is_none() {
+            return self.fail("whitespace");
+        }
+        self.whsp();
+        Ok(())
+    }
+
+    fn arrow(&mut self) -> Step<()> {
+        if self.eat("→") || self.eat("->") {
+            Ok(())
+        } else {
+            Err(Failure::Backtrack)
+        }
+    }
+
+    fn label(&mut self) -> Step<Label> {
+        let (start, mark) = (self.pos, self.token_mark());
+        if self.eat("`") {
+            let s =
+                self.take_while1("a quoted label", TokenKind::Label, |c| {
+                    (' ' <= c && c <= '_') || ('a' <= c && c <= '~')
+                })?;
+            self.token("`")?;
+            self.join_tokens(mark, start, TokenKind::Label);
+            return Ok(Label::from(s.to_owned()));
+        }
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+            _ => return self.fail("a label"),
+        }
+        let s = self.peek_while(|c| {
+            c.is_ascii_alphanumeric() || c == '-' || c == '/' || c == '_'
+        });
+        if KEYWORDS.contains(&s) {
+            return self.fail("a label");
+        }
+        self.advance(s.len(), TokenKind::Label);
+        Ok(Label::from(s.to_owned()))
+    }
+
+    fn nonreserved_label(&mut self) -> Step<Label> {
+        let start = self.pos;
+        let l = self.label()?;
+        if crate::Builtin::parse(&String::from(&l)).is_some() {
+            let message =
+                "Builtin names are not allowed as bound variables".to_owned();
+            return self.invalid(start, message);
+        }
+        Ok(l)
+    }
+
+    fn expression(&mut self) -> Step<ParsedSubExpr> {
+        let start = self.pos;
+        let alternative = match self.peek() {
+            Some('λ') | Some('\\') => self.attempt(|p| p.lambda(start))?,
+            Some('i') => self.attempt(|p| p.if_then_else(start))?,
+            Some('l') => self.attempt(|p| p.let_in(start))?,
+            Some('∀') | Some('f') => self.attempt(|p| p.forall(start))?,
+            Some('m') => self.attempt(|p| p.merge(start))?,
+            Some('[') => self.attempt(|p| p.empty_collection(start))?,
+            _ => None,
+        };
+        match alternative {
+            Some(e) => Ok(e),
+            None => self.annotated_expression(start),
+        }
+    }
+
+    fn lambda(&mut self, start: usize) -> Step<ParsedSubExpr> {
+        if !(self.eat("λ") || self.eat("\\")) {
+            return Err(Failure::Backtrack);
+        }
+        let (l, typ, body) = self.binder()?;
+        Ok(self.noted(start, Lam(l, typ, body)))
+    }
+
+    fn forall(&mut self, start: usize) -> Step<ParsedSubExpr> {
+        if !(self.eat("∀") || self.eat("forall")) {
+            return Err(Failure::Backtrack);
+        }
+        let (l, typ, body) = self.binder()?;
+        Ok(self.noted(start, Pi(l, typ, body)))
+    }
+
+    /// The part of a lambda or forall after the keyword
+    fn binder(&mut self) -> Step<(Label, ParsedSubExpr, ParsedSubExpr)> {
+        self.whsp();
+        self.token("(")?;
+        self.whsp();
+        let l = self.nonreserved_label()?;
+        self.whsp();
+        self.token(":")?;
+        self.whsp1()?;
+        let typ = self.expression()?;
+        self.whsp();
+        self.token(")")?;
+        self.whsp();
+        self.arrow()?;
+        self.whsp();
+        let body = self.expression()?;
+        Ok((l, typ, body))
+    }
+
+    fn if_then_else(&mut self, start: usize) -> Step<ParsedSubExpr> {
+        self.token("if")?;
+        self.whsp1()?;
+        let cond = self.expression()?;
+        self.whsp();
+        self.token("then")?;
+        self.whsp1()?;
+        let left = self.expression()?;
+        self.whsp();
+        self.token("else")?;
+        self.whsp1()?;
+        let right = self.expression()?;
+        Ok(self.noted(start, BoolIf(cond, left, right)))
+    }
+
+    fn let_in(&mut self, start: usize) -> Step<ParsedSubExpr> {
+        let mut bindings = vec![];
+        while let Some(binding) = self.attempt(Self::let_binding)? {
+            bindings.push(binding);
+        }
+        if bindings.is_empty() {
+            return Err(Failure::Backtrack);
+        }
+        self.token("in")?;
+        self.whsp1()?;
+        let body = self.expression()?;
+        // Nest the bindings the same way as the pest parser
+        let e = bindings
+            .into_iter()
+            .fold(body, |acc, (x, t, v)| rc(Let(x, t, v, acc)));
+        Ok(rc(Note(self.span(start), e)))
+    }
+
+    fn let_binding(
+        &mut self,
+    ) -> Step<(Label, Option<ParsedSubExpr>, ParsedSubExpr)> {
+        self.token("let")?;
+        self.whsp1()?;
+        let name = self.nonreserved_label()?;
+        self.whsp();
+        let annot = self.attempt(|p| {
+            p.token(":")?;
+            p.whsp1()?;
+            let annot = p.expression()?;
+            p.whsp();
+            Ok(annot)
+        })?;
+        self.token("=")?;
+        self.whsp();
+        let e = self.expression()?;
+        self.whsp();
+        Ok((name, annot, e))
+    }
+
+    fn merge(&mut self, start: usize) -> Step<ParsedSubExpr> {
+        self.token("merge")?;
+        self.whsp1()?;
+        let x = self.import_expression()?;
+        self.whsp1()?;
+        let y = self.import_expression()?;
+        let z = self.attempt(|p| {
+            p.whsp();
+            p.token(":")?;
+            p.whsp1()?;
+            p.application_expression()
+        })?;
+        Ok(self.noted(start, Merge(x, y, z)))
+    }
+
+    fn empty_collection(&mut self, start: usize) -> Step<ParsedSubExpr> {
+        self.token("[")?;
+        self.whsp();
+        self.token("]")?;
+        self.whsp();
+        self.token(":")?;
+        self.whsp1()?;
+        let optional = if self.eat("List") {
+            false
+        } else if self.eat("Optional") {
+            true
+        } else {
+            return Err(Failure::Backtrack);
+        };
+        self.whsp();
+        let t = self.import_expression()?;
+        if optional {
+            Ok(self.noted(start, EmptyOptionalLit(t)))
+        } else {
+            Ok(self.noted(start, EmptyListLit(t)))
+        }
+    }
+
+    /// Covers the `a -> b`, `[x] : Optional T` and `a : T` alternatives,
+    /// which all start with an operator expression
+    fn annotated_expression(&mut self, start: usize) -> Step<ParsedSubExpr> {
+        let e = self.operator_expression()?;
+        let body = self.attempt(|p| {
+            p.whsp();
+            p.arrow()?;
+            p.whsp();
+            p.expression()
+        })?;
+        if let Some(body) = body {
+            return Ok(self.noted(start, Pi("_".into(), e, body)));
+        }
+        if let Some(x) = self.single_element_list(&e, start) {
+            let t = self.attempt(|p| {
+                p.whsp();
+                p.token(":")?;
+                p.whsp1()?;
+                p.token("Optional")?;
+                p.whsp();
+                p.import_expression()
+            })?;
+            if let Some(t) = t {
+                let e = Annot(rc(NEOptionalLit(x)), t);
+                return Ok(self.noted(start, e));
+            }
+        }
+        let annot = self.attempt(|p| {
+            p.whsp();
+            p.token(":")?;
+            p.whsp1()?;
+            p.expression()
+        })?;
+        match annot {
+            Some(t) => Ok(self.noted(start, Annot(e, t))),
+            None => Ok(e),
+        }
+    }
+
+    /// The element of `e` if it is exactly a list literal `[x]` spanning the
+    /// input since `start`
+    fn single_element_list(
+        &self,
+        e: &ParsedSubExpr,
+        start: usize,
+    ) -> Option<ParsedSubExpr> {
+        match e.as_ref() {
+            Note(span, e) if span.start == start && span.end == self.pos => {
+                match e.as_ref() {
+                    NEListLit(xs) if xs.len() == 1 => Some(xs[0].clone()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn operator_expression(&mut self) -> Step<ParsedSubExpr> {
+        self.binop_expression(0)
+    }
+
+    fn binop_expression(&mut self, level: usize) -> Step<ParsedSubExpr> {
+        let (tokens, whsp1, op) = match OPERATORS.get(level) {
+            Some(&operator) => operator,
+            None => return self.application_expression(),
+        };
+        let start = self.pos;
+        let mut acc = self.binop_expression(level + 1)?;
+        loop {
+            let e = self.attempt(|p| {
+                p.whsp();
+                if !tokens.iter().any(|t| p.eat(*t)) {
+                    return Err(Failure::Backtrack);
+                }
+                if whsp1 {
+                    p.whsp1()?;
+                } else {
+                    p.whsp();
+                }
+                p.binop_expression(level + 1)
+            })?;
+            match e {
+                Some(e) => acc = self.noted(start, BinOp(op, acc, e)),
+                None => break,
+            }
+        }
+        Ok(acc)
+    }
+
+    fn application_expression(&mut self) -> Step<ParsedSubExpr> {
+        let start = self.pos;
+        let some = self
+            .attempt(|p| {
+                p.token("Some")?;
+                p.whsp1()
+            })?
+            .is_some();
+        let first = self.import_expression()?;
+        let mut args = vec![];
+        while let Some(arg) = self.attempt(|p| {
+            p.whsp1()?;
+            p.import_expression()
+        })? {
+            args.push(arg);
+        }
+        if some {
+            Ok(self.noted(start, app(NEOptionalLit(first), args)))
+        } else if args.is_empty() {
+            Ok(first)
+        } else if is_builtin(first.as_ref(), crate::Builtin::OptionalNone) {
+            let mut args = args.into_iter();
+            let e = args.next().unwrap();
+            Ok(self.noted(start, app(EmptyOptionalLit(e), args.collect())))
+        } else {
+            Ok(self.noted(start, app(first.as_ref().clone(), args)))
+        }
+    }
+
+    fn import_expression(&mut self) -> Step<ParsedSubExpr> {
+        let start = self.pos;
+        if let Some(import) = self.attempt(Self::import)? {
+            return Ok(self.noted(start, Embed(import)));
+        }
+        self.selector_expression()
+    }
+
+    fn selector_expression(&mut self) -> Step<ParsedSubExpr> {
+        let start = self.pos;
+        let mut e = self.primitive_expression()?;
+        while let Some(selector) = self.attempt(|p| {
+            p.whsp();
+            p.token(".")?;
+            p.whsp();
+            p.selector()
+        })? {
+            e = match selector {
+                Selector::Field(l) => self.noted(start, Field(e, l)),
+                Selector::Projection(ls) => {
+                    self.noted(start, Projection(e, ls))
+                }
+            };
+        }
+        Ok(e)
+    }
+
+    fn selector(&mut self) -> Step<Selector> {
+        if !self.eat("{") {
+            return Ok(Selector::Field(self.label()?));
+        }
+        self.whsp();
+        let mut labels = vec![];
+        if let Some(l) = self.attempt(Self::label)? {
+            labels.push(l);
+            self.whsp();
+            while let Some(l) = self.attempt(|p| {
+                p.token(",")?;
+                p.whsp();
+                let l = p.label()?;
+                p.whsp();
+                Ok(l)
+            })? {
+                labels.push(l);
+            }
+        }
+        self.token("}")?;
+        Ok(Selector::Projection(labels))
+    }
+
+    fn primitive_expression(&mut self) -> Step<ParsedSubExpr> {
+        let (start, mark) = (self.pos, self.token_mark());
+        let e = match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let e = match self.attempt(Self::numeric_double_literal)? {
+                    Some(n) => DoubleLit(n),
+                    None => NaturalLit(self.natural_literal()?),
+                };
+                self.join_tokens(mark, start, TokenKind::Literal);
+                e
+            }
+            Some('+') | Some('-') => {
+                let e = if let Some(n) =
+                    self.attempt(Self::numeric_double_literal)?
+                {
+                    DoubleLit(n)
+                } else if self.eat("-Infinity") {
+                    DoubleLit(std::f64::NEG_INFINITY.into())
+                } else {
+                    IntegerLit(self.integer_literal()?)
+                };
+                self.join_tokens(mark, start, TokenKind::Literal);
+                e
+            }
+            Some('"') => TextLit(self.double_quote_literal()?),
+            Some('\'') => TextLit(self.single_quote_literal()?),
+            Some('{') => self.record_type_or_literal()?,
+            Some('<') => self.union_type_or_literal()?,
+            Some('[') => self.non_empty_list_literal()?,
+            Some('(') => {
+                self.token("(")?;
+                self.whsp();
+                let e = self.expression()?;
+                self.whsp();
+                self.token(")")?;
+                return Ok(e);
+            }
+            _ => {
+                if self.eat("Infinity") {
+                    DoubleLit(std::f64::INFINITY.into())
+                } else if self.eat("NaN") {
+                    DoubleLit(std::f64::NAN.into())
+                } else {
+                    self.identifier()?
+                }
+            }
+        };
+        Ok(self.noted(start, e))
+    }
+
+    fn digits(&mut self) -> Step<&'a str> {
+        self.take_while1("a digit", TokenKind::Literal, |c| c.is_ascii_digit())
+    }
+
+    fn numeric_double_literal(&mut self) -> Step<core::Double> {
+        let start = self.pos;
+        let _ = self.eat("+") || self.eat("-");
+        self.digits()?;
+        if self.eat(".") {
+            self.digits()?;
+            self.attempt(Self::exponent)?;
+        } else {
+            self.exponent()?;
+        }
+        let s = &self.input[start..self.pos];
+        match s.parse::<f64>() {
+            Ok(x) if x.is_infinite() => self.invalid(
+                start,
+                format!("Overflow while parsing double literal '{}'", s),
+            ),
+            Ok(x) => Ok(NaiveDouble::from(x)),
+            Err(e) => self.invalid(start, format!("{}", e)),
+        }
+    }
+
+    fn exponent(&mut self) -> Step<()> {
+        if !(self.eat("e") || self.eat("E")) {
+            return Err(Failure::Backtrack);
+        }
+        let _ = self.eat("+") || self.eat("-");
+        self.digits()?;
+        Ok(())
+    }
+
+    fn natural_literal(&mut self) -> Step<core::Natural> {
+        let start = self.pos;
+        match self.digits()?.parse() {
+            Ok(n) => Ok(n),
+            Err(e) => self.invalid(start, format!("{}", e)),
+        }
+    }
+
+    fn integer_literal(&mut self) -> Step<core::Integer> {
+        let start = self.pos;
+        let negative = if self.eat("-") {
+            true
+        } else if self.eat("+") {
+            false
+        } else {
+            return Err(Failure::Backtrack);
+        };
+        let n: core::Integer = match self.digits()?.parse() {
+            Ok(n) => n,
+            Err(e) => return self.invalid(start, format!("{}", e)),
+        };
+        Ok(if negative { -n } else { n })
+    }
+
+    fn interpolation(&mut self) -> Step<ParsedSubExpr> {
+        self.token("${")?;
+        self.whsp();
+        let e = self.expression()?;
+        self.whsp();
+        self.token("}")?;
+        Ok(e)
+    }
+
+    fn double_quote_literal(&mut self) -> Step<ParsedText> {
+        self.token("\"")?;
+        let mut chunks = vec![];
+        let mut text = String::new();
+        loop {
+            if self.rest().starts_with("${") {
+                if let Some(e) = self.attempt(Self::interpolation)? {
+                    let text = std::mem::replace(&mut text, String::new());
+                    chunks.push(InterpolatedTextContents::Text(text));
+                    chunks.push(InterpolatedTextContents::Expr(e));
+                    continue;
+                }
+            }
+            match self.peek() {
+                Some('"') => {
+                    self.advance(1, TokenKind::Symbol);
+                    break;
+                }
+                Some('\\') => {
+                    self.advance(1, TokenKind::Literal);
+                    self.double_quote_escaped(&mut text)?;
+                }
+                Some(c) if is_double_quote_char(c) => {
+                    self.advance(c.len_utf8(), TokenKind::Literal);
+                    text.push(c);
+                }
+                _ => return self.fail("a text literal character"),
+            }
+        }
+        chunks.push(InterpolatedTextContents::Text(text));
+        Ok(chunks.into_iter().collect())
+    }
+
+    fn double_quote_escaped(&mut self, text: &mut String) -> Step<()> {
+        let start = self.pos;
+        let c = match self.peek() {
+            Some(c @ '"') | Some(c @ '$') | Some(c @ '\\') | Some(c @ '/') => c,
+            Some('b') => '\u{0008}',
+            Some('f') => '\u{000C}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                self.advance(1, TokenKind::Literal);
+                let hex = self
+                    .rest()
+                    .get(..4)
+                    .filter(|s| s.chars().all(|c| c.is_ascii_hexdigit()));
+                let hex = match hex {
+                    Some(hex) => hex,
+                    None => return self.fail("four hexadecimal digits"),
+                };
+                self.advance(4, TokenKind::Literal);
+                let c = u32::from_str_radix(hex, 16).unwrap();
+                match std::char::from_u32(c) {
+                    Some(c) => text.push(c),
+                    None => {
+                        let message = format!("Invalid escape '\\u{}'", hex);
+                        return self.invalid(start, message);
+                    }
+                }
+                return Ok(());
+            }
+            _ => return self.fail("an escape sequence"),
+        };
+        self.advance(1, TokenKind::Literal);
+        text.push(c);
+        Ok(())
+    }
+
+    fn single_quote_literal(&mut self) -> Step<ParsedText> {
+        self.token("''")?;
+        match self.rest() {
+            s if s.starts_with('\n') => self.advance(1, TokenKind::Literal),
+            s if s.starts_with("\r\n") => self.advance(2, TokenKind::Literal),
+            _ => return self.fail("a newline"),
+        }
+        // The number of spaces each line starts with, and the rest of it
+        let mut lines: Vec<(usize, Vec<ParsedTextContents>)> =
+            vec![(0, vec![])];
+        loop {
+            let (text, len) = if self.rest().starts_with("${") {
+                match self.attempt(Self::interpolation)? {
+                    Some(e) => {
+                        let line = &mut lines.last_mut().unwrap().1;
+                        line.push(InterpolatedTextContents::Expr(e));
+                        continue;
+                    }
+                    None => ("$", 1),
+                }
+            } else if self.rest().starts_with("'''") {
+                ("''", 3)
+            } else if self.rest().starts_with("''${") {
+                ("${", 4)
+            } else if self.eat("''") {
+                break;
+            } else if self.rest().starts_with("\r\n") {
+                ("\r\n", 2)
+            } else if self.rest().starts_with('\n') {
+                self.advance(1, TokenKind::Literal);
+                lines.push((0, vec![]));
+                continue;
+            } else {
+                match self.peek() {
+                    Some(c) if c == '\t' || c >= ' ' => {
+                        (&self.rest()[..c.len_utf8()], c.len_utf8())
+                    }
+                    _ => return self.fail("a text literal character"),
+                }
+            };
+            self.advance(len, TokenKind::Literal);
+            let (indent, line) = lines.last_mut().unwrap();
+            if text == " " && line.is_empty() {
+                *indent += 1;
+            } else {
+                line.push(InterpolatedTextContents::Text(text.to_owned()));
+            }
+        }
+        let min_indent = lines.iter().map(|(indent, _)| *indent).min().unwrap();
+        let newline = InterpolatedTextContents::Text("\n".to_owned());
+        let mut chunks = vec![];
+        for (i, (indent, line)) in lines.into_iter().enumerate() {
+            if i > 0 {
+                chunks.push(newline.clone());
+            }
+            let indent = " ".repeat(indent - min_indent);
+            chunks.push(InterpolatedTextContents::Text(indent));
+            chunks.extend(line);
+        }
+        Ok(chunks.into_iter().collect())
+    }
+
+    fn record_type_or_literal(&mut self) -> Step<ParsedExpr> {
+        self.token("{")?;
+        self.whsp();
+        let e = if self.eat("=") {
+            RecordLit(BTreeMap::new())
+        } else {
+            match self.attempt(Self::non_empty_record_type_or_literal)? {
+                Some(e) => e,
+                None => RecordType(BTreeMap::new()),
+            }
+        };
+        self.whsp();
+        self.token("}")?;
+        Ok(e)
+    }
+
+    fn non_empty_record_type_or_literal(&mut self) -> Step<ParsedExpr> {
+        let first_label = self.label()?;
+        self.whsp();
+        let separator = if self.eat("=") {
+            "="
+        } else if self.eat(":") {
+            ":"
+        } else {
+            return Err(Failure::Backtrack);
+        };
+        let first_expr = self.record_entry_value(separator)?;
+        let mut map = BTreeMap::new();
+        while let Some((l, e)) = self.attempt(|p| {
+            p.whsp();
+            p.token(",")?;
+            p.whsp();
+            let l = p.label()?;
+            p.whsp();
+            p.token(separator)?;
+            Ok((l, p.record_entry_value(separator)?))
+        })? {
+            map.insert(l, e);
+        }
+        map.insert(first_label, first_expr);
+        if separator == "=" {
+            Ok(RecordLit(map))
+        } else {
+            Ok(RecordType(map))
+        }
+    }
+
+    fn record_entry_value(&mut self, separator: &str) -> Step<ParsedSubExpr> {
+        if separator == "=" {
+            self.whsp();
+        } else {
+            self.whsp1()?;
+        }
+        self.expression()
+    }
+
+    fn union_type_or_literal(&mut self) -> Step<ParsedExpr> {
+        self.token("<")?;
+        self.whsp();
+        let e = match self.attempt(Self::non_empty_union_type_or_literal)? {
+            Some(e) => e,
+            None => UnionType(BTreeMap::new()),
+        };
+        self.whsp();
+        self.token(">")?;
+        Ok(e)
+    }
+
+    fn non_empty_union_type_or_literal(&mut self) -> Step<ParsedExpr> {
+        let mut types = vec![];
+        let mut alternative = self.union_alternative()?;
+        loop {
+            match alternative {
+                UnionAlternative::Type(l, t) => {
+                    types.push((l, t));
+                    let next = self.attempt(|p| {
+                        p.whsp();
+                        p.token("|")?;
+                        p.whsp();
+                        p.union_alternative()
+                    })?;
+                    match next {
+                        Some(next) => alternative = next,
+                        None => {
+                            let map = union_entries(vec![], types);
+                            return Ok(UnionType(map));
+                        }
+                    }
+                }
+                UnionAlternative::Value(l, e, entries) => {
+                    let map = union_entries(entries, types);
+                    return Ok(UnionLit(l, e, map));
+                }
+            }
+        }
+    }
+
+    fn union_alternative(&mut self) -> Step<UnionAlternative> {
+        let l = self.label()?;
+        self.whsp();
+        let e = self.attempt(|p| {
+            p.token("=")?;
+            p.whsp();
+            p.expression()
+        })?;
+        if let Some(e) = e {
+            let mut entries = vec![];
+            while let Some(entry) = self.attempt(|p| {
+                p.whsp();
+                p.token("|")?;
+                p.whsp();
+                let l = p.label()?;
+                p.whsp();
+                p.token(":")?;
+                p.whsp1()?;
+                Ok((l, p.expression()?))
+            })? {
+                entries.push(entry);
+            }
+            return Ok(UnionAlternative::Value(l, e, entries));
+        }
+        self.token(":")?;
+        self.whsp1()?;
+        Ok(UnionAlternative::Type(l, self.expression()?))
+    }
+
+    fn non_empty_list_literal(&mut self) -> Step<ParsedExpr> {
+        self.token("[")?;
+        self.whsp();
+        let mut items = vec![self.expression()?];
+        self.whsp();
+        while let Some(e) = self.attempt(|p| {
+            p.token(",")?;
+            p.whsp();
+            let e = p.expression()?;
+            p.whsp();
+            Ok(e)
+        })? {
+            items.push(e);
+        }
+        self.token("]")?;
+        Ok(NEListLit(items))
+    }
+
+    fn identifier(&mut self) -> Step<ParsedExpr> {
+        let l = self.label()?;
+        let idx_start = self.pos;
+        let idx = self.attempt(|p| {
+            p.whsp();
+            p.token("@")?;
+            p.whsp();
+            p.natural_literal()
+        })?;
+        let name = String::from(&l);
+        if let Some(b) = crate::Builtin::parse(name.as_str()) {
+            return Ok(Builtin(b));
+        }
+        Ok(match name.as_str() {
+            "True" => BoolLit(true),
+            "False" => BoolLit(false),
+            "Type" => Const(crate::Const::Type),
+            "Kind" => Const(crate::Const::Kind),
+            "Sort" => Const(crate::Const::Sort),
+            _ => match idx {
+                None => Var(V(l, 0)),
+                Some(idx) => match idx.to_usize() {
+                    Some(idx) => Var(V(l, idx)),
+                    None => {
+                        let message =
+                            format!("Variable index too large: {}", idx);
+                        return self.invalid(idx_start, message);
+                    }
+                },
+            },
+        })
+    }
+
+    fn import(&mut self) -> Step<Import> {
+        let location_hashed = self.import_hashed()?;
+        let raw_text = self.attempt(|p| {
+            p.whsp();
+            p.token("as")?;
+            p.whsp1()?;
+            p.token("Text")
+        })?;
+        let mode = match raw_text {
+            Some(()) => ImportMode::RawText,
+            None => ImportMode::Code,
+        };
+        Ok(Import {
+            mode,
+            location_hashed,
+        })
+    }
+
+    fn import_hashed(&mut self) -> Step<ImportHashed> {
+        let location = self.import_type()?;
+        let hash = self.attempt(|p| {
+            p.whsp1()?;
+            p.hash()
+        })?;
+        Ok(ImportHashed { location, hash })
+    }
+
+    fn hash(&mut self) -> Step<Hash> {
+        let (start, mark) = (self.pos, self.token_mark());
+        self.token("sha256:")?;
+        let hash = self
+            .rest()
+            .get(..64)
+            .filter(|s| s.chars().all(|c| c.is_ascii_hexdigit()));
+        match hash {
+            Some(hash) => {
+                self.advance(64, TokenKind::Literal);
+                self.join_tokens(mark, start, TokenKind::Literal);
+                Ok(Hash {
+                    protocol: "sha256".to_owned(),
+                    hash: hash.to_owned(),
+                })
+            }
+            None => self.fail("a sha256 hash"),
+        }
+    }
+
+    fn import_type(&mut self) -> Step<ImportLocation> {
+        use crate::FilePrefix::*;
+        if self.eat("missing") {
+            return Ok(ImportLocation::Missing);
+        }
+        let (start, mark) = (self.pos, self.token_mark());
+        let prefix = if self.eat("..") {
+            Parent
+        } else if self.eat(".") {
+            Here
+        } else if self.eat("~") {
+            Home
+        } else if self.rest().starts_with('/') {
+            Absolute
+        } else if self.rest().starts_with("http") {
+            return Ok(ImportLocation::Remote(self.http()?));
+        } else if self.eat("env:") {
+            let env = self.env()?;
+            self.join_tokens(mark, start, TokenKind::Literal);
+            return Ok(ImportLocation::Env(env));
+        } else {
+            return Err(Failure::Backtrack);
+        };
+        let path = self.path()?;
+        self.join_tokens(mark, start, TokenKind::Literal);
+        Ok(ImportLocation::Local(prefix, path))
+    }
+
+    fn path(&mut self) -> Step<PathBuf> {
+        let mut components = vec![];
+        while let Some(c) = self.attempt(Self::path_component)? {
+            components.push(c);
+        }
+        if components.is_empty() {
+            return self.fail("a path");
+        }
+        Ok(components.into_iter().collect())
+    }
+
+    fn path_component(&mut self) -> Step<&'a str> {
+        self.token("/")?;
+        if !self.eat("\"") {
+            return self.take_while1(
+                "a path component",
+                TokenKind::Literal,
+                is_path_char,
+            );
+        }
+        let s =
+            self.take_while1("a path component", TokenKind::Literal, |c| {
+                c != '"' && c != '/' && (c >= ' ')
+            })?;
+        self.token("\"")?;
+        Ok(s)
+    }
+
+    fn http(&mut self) -> Step<URL> {
+        let (start, mark) = (self.pos, self.token_mark());
+        let scheme = if self.eat("https") {
+            Scheme::HTTPS
+        } else if self.eat("http") {
+            Scheme::HTTP
+        } else {
+            return Err(Failure::Backtrack);
+        };
+        self.token("://")?;
+        let authority = self
+            .take_while(TokenKind::Literal, is_authority_char)
+            .to_owned();
+        let path = self.path()?;
+        let query = self.attempt(|p| {
+            p.token("?")?;
+            Ok(p.take_while(TokenKind::Literal, is_query_char).to_owned())
+        })?;
+        self.join_tokens(mark, start, TokenKind::Literal);
+        let headers = self.attempt(|p| {
+            p.whsp();
+            p.token("using")?;
+            let headers = p.attempt(|p| {
+                p.whsp1()?;
+                p.import_hashed()
+            })?;
+            if let Some(headers) = headers {
+                return Ok(headers);
+            }
+            p.whsp();
+            p.token("(")?;
+            p.whsp();
+            let headers = p.import_hashed()?;
+            p.whsp();
+            p.token(")")?;
+            Ok(headers)
+        })?;
+        Ok(URL {
+            scheme,
+            authority,
+            path,
+            query,
+            headers: headers.map(Box::new),
+        })
+    }
+
+    fn env(&mut self) -> Step<String> {
+        if !self.eat("\"") {
+            match self.peek() {
+                Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+                _ => return self.fail("an environment variable"),
+            }
+            let s = self.take_while(TokenKind::Literal, |c| {
+                c.is_ascii_alphanumeric() || c == '_'
+            });
+            return Ok(s.to_owned());
+        }
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.advance(1, TokenKind::Literal);
+                    match self.peek() {
+                        Some(c) if "\"\\abfnrtv".contains(c) => {
+                            self.advance(1, TokenKind::Literal)
+                        }
+                        _ => return self.fail("an escape sequence"),
+                    }
+                }
+                Some(c) if c != '"' && c != '=' && ' ' <= c && c <= '~' => {
+                    self.advance(1, TokenKind::Literal)
+                }
+                _ => break,
+            }
+        }
+        if self.pos == start {
+            return self.fail("an environment variable");
+        }
+        let s = self.input[start..self.pos].to_owned();
+        self.token("\"")?;
+        Ok(s)
+    }
+}
+
+/// Builds the alternatives of a union the same way as the pest parser: the
+/// first of repeated types before a literal alternative wins, and the last
+/// of those after it
+fn union_entries(
+    after: Vec<(Label, ParsedSubExpr)>,
+    before: Vec<(Label, ParsedSubExpr)>,
+) -> BTreeMap<Label, ParsedSubExpr> {
+    let mut map: BTreeMap<_, _> = after.into_iter().collect();
+    for (l, t) in before.into_iter().rev() {
+        map.insert(l, t);
+    }
+    map
+}
+
+/// The length of the whitespace or comment at the start of `s`, if any
+fn whitespace_chunk(s: &str) -> Option<usize> {
+    if s.starts_with(' ') || s.starts_with('\t') || s.starts_with('\n') {
+        Some(1)
+    } else if s.starts_with("\r\n") {
+        Some(2)
+    } else if s.starts_with("--") {
+        let len = s
+            .find(|c: char| !(c == '\t' || c >= ' '))
+            .unwrap_or(s.len());
+        if s[len..].starts_with('\n') {
+            Some(len + 1)
+        } else if s[len..].starts_with("\r\n") {
+            Some(len + 2)
+        } else {
+            None
+        }
+    } else if s.starts_with("{-") {
+        block_comment(s)
+    } else {
+        None
+    }
+}
+
+/// The length of the possibly nested block comment at the start of `s`
+fn block_comment(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < s.len() {
+        let rest = &s[i..];
+        if rest.starts_with("{-") {
+            depth += 1;
+            i += 2;
+        } else if rest.starts_with("-}") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return Some(i);
+            }
+        } else if rest.starts_with("\r\n") {
+            i += 2;
+        } else {
+            match rest.chars().next() {
+                Some(c) if c == '\t' || c == '\n' || c >= ' ' => {
+                    i += c.len_utf8()
+                }
+                _ => return None,
+            }
+        }
+    }
+    None
+}
+
+fn is_double_quote_char(c: char) -> bool {
+    c >= ' ' && c != '"' && c != '\\'
+}
+
+fn is_path_char(c: char) -> bool {
+    match c {
+        '!' | '$'..='\'' | '*' | '+' | '-' | '.' | '0'..=';' | '=' => true,
+        '@'..='Z' | '^'..='z' | '|' | '~' => true,
+        _ => false,
+    }
+}
+
+fn is_unreserved_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~".contains(c)
+}
+
+fn is_authority_char(c: char) -> bool {
+    is_unreserved_char(c) || "!$&'()*+,;=:@%[]".contains(c)
+}
+
+fn is_query_char(c: char) -> bool {
+    is_unreserved_char(c) || "!$&'()*+,;=:@%/?".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_expr;
+    use crate::pest_parser::parse_expr_with_pest;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_same_as_pest() {
+        let exprs = &[
+            "λ(x : Natural) → x + 1",
+            "∀(a : Type) → a -> List a",
+            "if True then 1 else 2",
+            "let x = 1 let y : Natural = x in y * 2",
+            "merge { Left = λ(x : Natural) → x } < Left = 1 | Right : Bool >",
+            "merge x y : Natural",
+            "[] : List Natural",
+            "[ 1, 2, 3 ] : List Natural",
+            "[ 1 ] : Optional Natural",
+            "Some 1",
+            "None Natural",
+            "{ x = 1, y = { z = +2 } }.y.z",
+            "{ x : Natural, y : Text }",
+            "{=} ∧ {} ⫽ {=} //\\\\ {}",
+            "< A : Natural | B : Text >",
+            "< >",
+            "r.{ x, y }",
+            "x@1 == y != z && a || b # c ++ d",
+            "1.5e-3 + -Infinity + NaN + -2 + 0",
+            "\"a${x}b\\n\\u0041\"",
+            "''\n  foo\n    ${bar}\n  '''baz''${\n  ''",
+            "./a/b.dhall ? ../c ? ~/d as Text ? /e/\"f g\"",
+            "https://example.com/a?b using ./headers ? env:HOME ? env:\"A B\"",
+            concat!(
+                "missing sha256:",
+                "00000000000000000000000000000000",
+                "00000000000000000000000000000000",
+            ),
+            "-- comment\n{- nested {- block -} comment -} `x y`",
+        ];
+        for s in exprs {
+            let expected = parse_expr_with_pest(s).unwrap().unnote();
+            assert_eq!(parse_expr(s).unwrap().unnote(), expected, "{}", s);
+        }
+        let invalid = &["λ(Natural : Type) → x", "1 +", "{ x = 1, }", "if"];
+        for s in invalid {
+            assert!(parse_expr(s).is_err(), "{}", s);
+        }
+    }
+    /// Every `.dhall` file below `dir`
+    fn dhall_files(dir: &Path, files: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dhall_files(&path, files);
+            } else if path.extension().map_or(false, |ext| ext == "dhall") {
+                files.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_as_pest_on_spec() {
+        let dir = Path::new("../dhall-lang/tests/parser");
+        // The spec submodule may not be checked out
+        if !dir.is_dir() {
+            return;
+        }
+        let mut files = vec![];
+        dhall_files(dir, &mut files);
+        for file in files {
+            let s = fs::read_to_string(&file).unwrap();
+            match parse_expr_with_pest(&s) {
+                Ok(expected) => {
+                    let e = parse_expr(&s)
+                        .unwrap_or_else(|err| panic!("{}: {}", file, err));
+                    assert_eq!(e.unnote(), expected.unnote(), "{}", file);
+                }
+                Err(_) => assert!(parse_expr(&s).is_err(), "{}", file),
+            }
+        }
+    }
+}
@@ -17,6 +17,17 @@ mod text;
 pub use crate::text::*;
 mod printer;
 pub use crate::printer::*;
+mod span;
+pub use crate::span::*;
 mod parser;
 pub use crate::parser::*;
+mod descent;
+#[cfg(any(test, feature = "pest-parser"))]
+mod pest_parser;
+#[cfg(any(test, feature = "pest-parser"))]
+pub use crate::pest_parser::parse_expr_with_pest;
+mod recovery;
+pub use crate::recovery::*;
+mod cst;
+pub use crate::cst::*;
 pub mod context;
@@ -1,34 +1,34 @@
-use itertools::Itertools;
-use pest::iterators::Pair;
-use pest::Parser;
-use std::collections::BTreeMap;
-use std::path::PathBuf;
-
-use dhall_parser::{DhallParser, Rule};
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
 
 use crate::*;
 
-// This file consumes the parse tree generated by pest and turns it into
-// our own AST. All those custom macros should eventually moved into
-// their own crate because they are quite general and useful. For now they
-// are here and hopefully you can figure out how they work.
-
 use crate::ExprF::*;
 
-type ParsedExpr = Expr<X, Import>;
-type ParsedSubExpr = SubExpr<X, Import>;
-type ParsedText = InterpolatedText<SubExpr<X, Import>>;
-type ParsedTextContents = InterpolatedTextContents<SubExpr<X, Import>>;
+pub(crate) type ParsedExpr = Expr<Span, Import>;
+pub(crate) type ParsedSubExpr = SubExpr<Span, Import>;
+pub(crate) type ParsedText = InterpolatedText<SubExpr<Span, Import>>;
+pub(crate) type ParsedTextContents =
+    InterpolatedTextContents<SubExpr<Span, Import>>;
+
+/// A syntax error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// Where in the input the error was found
+    pub span: Span,
+}
 
-pub type ParseError = pest::error::Error<Rule>;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.span)
+    }
+}
 
-pub type ParseResult<T> = Result<T, ParseError>;
+impl std::error::Error for ParseError {}
 
-#[derive(Debug)]
-enum Either<A, B> {
-    Left(A),
-    Right(B),
-}
+pub type ParseResult<T> = Result<T, ParseError>;
 
 impl crate::Builtin {
     pub fn parse(s: &str) -> Option<Self> {
@@ -67,836 +67,25 @@ impl crate::Builtin {
     }
 }
 
-pub fn custom_parse_error(pair: &Pair<Rule>, msg: String) -> ParseError {
-    let msg =
-        format!("{} while matching on:\n{}", msg, debug_pair(pair.clone()));
-    let e = pest::error::ErrorVariant::CustomError { message: msg };
-    pest::error::Error::new_from_span(e, pair.as_span())
-}
-
-fn debug_pair(pair: Pair<Rule>) -> String {
-    use std::fmt::Write;
-    let mut s = String::new();
-    fn aux(s: &mut String, indent: usize, prefix: String, pair: Pair<Rule>) {
-        let indent_str = "| ".repeat(indent);
-        let rule = pair.as_rule();
-        let contents = pair.as_str();
-        let mut inner = pair.into_inner();
-        let mut first = true;
-        while let Some(p) = inner.next() {
-            if first {
-                first = false;
-                let last = inner.peek().is_none();
-                if last && p.as_str() == contents {
-                    let prefix = format!("{}{:?} > ", prefix, rule);
-                    aux(s, indent, prefix, p);
-                    continue;
-                } else {
-                    writeln!(
-                        s,
-                        r#"{}{}{:?}: "{}""#,
-                        indent_str, prefix, rule, contents
-                    )
-                    .unwrap();
-                }
-            }
-            aux(s, indent + 1, "".into(), p);
-        }
-        if first {
-            writeln!(
-                s,
-                r#"{}{}{:?}: "{}""#,
-                indent_str, prefix, rule, contents
-            )
-            .unwrap();
-        }
-    }
-    aux(&mut s, 0, "".into(), pair);
-    s
-}
-
-macro_rules! make_parser {
-    (@pattern, rule, $name:ident) => (Rule::$name);
-    (@pattern, token_rule, $name:ident) => (Rule::$name);
-    (@pattern, rule_group, $name:ident) => (_);
-    (@filter, rule) => (true);
-    (@filter, token_rule) => (true);
-    (@filter, rule_group) => (false);
-
-    (@body,
-        $pair:expr,
-        $children:expr,
-        rule!( $name:ident<$o:ty>; $($args:tt)* )
-    ) => (
-        make_parser!(@body,
-            $pair,
-            $children,
-            rule!( $name<$o> as $name; $($args)* )
-        )
-    );
-    (@body,
-        $pair:expr,
-        $children:expr,
-        rule!(
-            $name:ident<$o:ty>
-            as $group:ident;
-            captured_str!($x:pat) => $body:expr
-        )
-    ) => ({
-        let $x = $pair.as_str();
-        let res: $o = $body;
-        Ok(ParsedValue::$group(res))
-    });
-    (@body,
-        $pair:expr,
-        $children:expr,
-        rule!(
-            $name:ident<$o:ty>
-            as $group:ident;
-            children!( $( [$($args:tt)*] => $body:expr ),* $(,)* )
-        )
-    ) => ({
-        #[allow(unused_imports)]
-        use ParsedValue::*;
-        #[allow(unreachable_code)]
-        let res: $o = iter_patterns::match_vec!($children;
-            $( [$($args)*] => $body, )*
-            [x..] => Err(
-                format!("Unexpected children: {:?}", x.collect::<Vec<_>>())
-            )?,
-        ).ok_or_else(|| -> String { unreachable!() })?;
-        Ok(ParsedValue::$group(res))
-    });
-    (@body,
-        $pair:expr,
-        $children:expr,
-        token_rule!($name:ident<$o:ty>)
-    ) => ({
-        Ok(ParsedValue::$name(()))
-    });
-    (@body, $pair:expr, $children:expr, rule_group!( $name:ident<$o:ty> )) => (
-        unreachable!()
-    );
-
-    ($( $submac:ident!( $name:ident<$o:ty> $($args:tt)* ); )*) => (
-        #[allow(non_camel_case_types, dead_code, clippy::large_enum_variant)]
-        #[derive(Debug)]
-        enum ParsedValue<'a> {
-            $( $name($o), )*
-        }
-
-        fn parse_any<'a>(pair: Pair<'a, Rule>, children: Vec<ParsedValue<'a>>)
-                -> Result<ParsedValue<'a>, String> {
-            match pair.as_rule() {
-                $(
-                    make_parser!(@pattern, $submac, $name)
-                    if make_parser!(@filter, $submac)
-                    => make_parser!(@body, pair, children,
-                                           $submac!( $name<$o> $($args)* ))
-                    ,
-                )*
-                r => Err(format!("Unexpected {:?}", r)),
-            }
-        }
-    );
-}
-
-// Non-recursive implementation to avoid stack overflows
-fn do_parse<'a>(initial_pair: Pair<'a, Rule>) -> ParseResult<ParsedValue<'a>> {
-    enum StackFrame<'a> {
-        Unprocessed(Pair<'a, Rule>),
-        Processed(Pair<'a, Rule>, usize),
-    }
-    use StackFrame::*;
-    let mut pairs_stack: Vec<StackFrame> =
-        vec![Unprocessed(initial_pair.clone())];
-    let mut values_stack: Vec<ParsedValue> = vec![];
-    while let Some(p) = pairs_stack.pop() {
-        match p {
-            Unprocessed(mut pair) => loop {
-                let mut pairs: Vec<_> = pair.clone().into_inner().collect();
-                let n_children = pairs.len();
-                if n_children == 1 && can_be_shortcutted(pair.as_rule()) {
-                    pair = pairs.pop().unwrap();
-                    continue;
-                } else {
-                    pairs_stack.push(Processed(pair, n_children));
-                    pairs_stack
-                        .extend(pairs.into_iter().map(StackFrame::Unprocessed));
-                    break;
-                }
-            },
-            Processed(pair, n) => {
-                let mut children: Vec<_> =
-                    values_stack.split_off(values_stack.len() - n);
-                children.reverse();
-                let val = match parse_any(pair.clone(), children) {
-                    Ok(v) => v,
-                    Err(msg) => Err(custom_parse_error(&pair, msg))?,
-                };
-                values_stack.push(val);
-            }
-        }
-    }
-    Ok(values_stack.pop().unwrap())
-}
-
-// List of rules that can be shortcutted if they have a single child
-fn can_be_shortcutted(rule: Rule) -> bool {
-    use Rule::*;
-    match rule {
-        expression
-        | import_alt_expression
-        | or_expression
-        | plus_expression
-        | text_append_expression
-        | list_append_expression
-        | and_expression
-        | combine_expression
-        | prefer_expression
-        | combine_types_expression
-        | times_expression
-        | equal_expression
-        | not_equal_expression
-        | application_expression
-        | selector_expression
-        | annotated_expression => true,
+/// Whether the expression is the given builtin, ignoring notes
+pub(crate) fn is_builtin(e: &ParsedExpr, b: crate::Builtin) -> bool {
+    match e {
+        Builtin(b2) => *b2 == b,
+        Note(_, e) => is_builtin(e.as_ref(), b),
         _ => false,
     }
 }
 
-make_parser! {
-    token_rule!(EOI<()>);
-
-    rule!(simple_label<Label>;
-        captured_str!(s) => Label::from(s.trim().to_owned())
-    );
-    rule!(quoted_label<Label>;
-        captured_str!(s) => Label::from(s.trim().to_owned())
-    );
-    rule!(label<Label>; children!(
-        [simple_label(l)] => l,
-        [quoted_label(l)] => l,
-    ));
-    rule!(nonreserved_label<Label>; children!(
-        [label(l)] => {
-            if crate::Builtin::parse(&String::from(&l)).is_some() {
-                Err(
-                    "Builtin names are not allowed as bound variables".to_string()
-                )?
-            }
-            l
-        },
-    ));
-
-    rule!(double_quote_literal<ParsedText>; children!(
-        [double_quote_chunk(chunks)..] => {
-            chunks.collect()
-        }
-    ));
-
-    rule!(double_quote_chunk<ParsedTextContents>; children!(
-        [interpolation(e)] => {
-            InterpolatedTextContents::Expr(rc(e))
-        },
-        [double_quote_escaped(s)] => {
-            InterpolatedTextContents::Text(s)
-        },
-        [double_quote_char(s)] => {
-            InterpolatedTextContents::Text(s.to_owned())
-        },
-    ));
-    rule!(double_quote_escaped<String>;
-        captured_str!(s) => {
-            match s {
-                "\"" => "\"".to_owned(),
-                "$" => "$".to_owned(),
-                "\\" => "\\".to_owned(),
-                "/" => "/".to_owned(),
-                "b" => "\u{0008}".to_owned(),
-                "f" => "\u{000C}".to_owned(),
-                "n" => "\n".to_owned(),
-                "r" => "\r".to_owned(),
-                "t" => "\t".to_owned(),
-                _ => {
-                    // "uXXXX"
-                    use std::convert::TryFrom;
-                    let c = u16::from_str_radix(&s[1..5], 16).unwrap();
-                    let c = char::try_from(u32::from(c)).unwrap();
-                    std::iter::once(c).collect()
-                }
-            }
-        }
-    );
-    rule!(double_quote_char<&'a str>;
-        captured_str!(s) => s
-    );
-
-    rule!(single_quote_literal<ParsedText>; children!(
-        [single_quote_continue(lines)] => {
-            let space = InterpolatedTextContents::Text(" ".to_owned());
-            let newline = InterpolatedTextContents::Text("\n".to_owned());
-            let min_indent = lines
-                .iter()
-                .map(|l| {
-                    l.iter().rev().take_while(|c| **c == space).count()
-                })
-                .min()
-                .unwrap();
-
-            lines
-                .into_iter()
-                .rev()
-                .map(|mut l| { l.split_off(l.len() - min_indent); l })
-                .intersperse(vec![newline])
-                .flat_map(|x| x.into_iter().rev())
-                .collect::<ParsedText>()
-        }
-    ));
-    rule!(single_quote_char<&'a str>;
-        captured_str!(s) => s
-    );
-    rule!(escaped_quote_pair<&'a str>;
-        captured_str!(_) => "''"
-    );
-    rule!(escaped_interpolation<&'a str>;
-        captured_str!(_) => "${"
-    );
-    rule!(interpolation<ParsedExpr>; children!(
-        [expression(e)] => e
-    ));
-
-    rule!(single_quote_continue<Vec<Vec<ParsedTextContents>>>; children!(
-        [interpolation(c), single_quote_continue(lines)] => {
-            let c = InterpolatedTextContents::Expr(rc(c));
-            let mut lines = lines;
-            lines.last_mut().unwrap().push(c);
-            lines
-        },
-        [escaped_quote_pair(c), single_quote_continue(lines)] => {
-            let c = InterpolatedTextContents::Text(c.to_owned());
-            let mut lines = lines;
-            lines.last_mut().unwrap().push(c);
-            lines
-        },
-        [escaped_interpolation(c), single_quote_continue(lines)] => {
-            let c = InterpolatedTextContents::Text(c.to_owned());
-            let mut lines = lines;
-            lines.last_mut().unwrap().push(c);
-            lines
-        },
-        [single_quote_char("\n"), single_quote_continue(lines)] => {
-            let mut lines = lines;
-            lines.push(vec![]);
-            lines
-        },
-        [single_quote_char(c), single_quote_continue(lines)] => {
-            let c = InterpolatedTextContents::Text(c.to_owned());
-            let mut lines = lines;
-            lines.last_mut().unwrap().push(c);
-            lines
-        },
-        [] => {
-            vec![vec![]]
-        },
-    ));
-
-    token_rule!(NaN<()>);
-    token_rule!(minus_infinity_literal<()>);
-    token_rule!(plus_infinity_literal<()>);
-
-    rule!(numeric_double_literal<core::Double>;
-        captured_str!(s) => {
-            let s = s.trim();
-            match s.parse::<f64>() {
-                Ok(x) if x.is_infinite() =>
-                    Err(format!("Overflow while parsing double literal '{}'", s))?,
-                Ok(x) => NaiveDouble::from(x),
-                Err(e) => Err(format!("{}", e))?,
-            }
-        }
-    );
-
-    rule!(double_literal<core::Double>; children!(
-        [numeric_double_literal(n)] => n,
-        [minus_infinity_literal(n)] => std::f64::NEG_INFINITY.into(),
-        [plus_infinity_literal(n)] => std::f64::INFINITY.into(),
-        [NaN(n)] => std::f64::NAN.into(),
-    ));
-
-    rule!(natural_literal<core::Natural>;
-        captured_str!(s) => {
-            s.trim()
-                .parse()
-                .map_err(|e| format!("{}", e))?
-        }
-    );
-
-    rule!(integer_literal<core::Integer>;
-        captured_str!(s) => {
-            s.trim()
-                .parse()
-                .map_err(|e| format!("{}", e))?
-        }
-    );
-
-    rule!(unquoted_path_component<&'a str>; captured_str!(s) => s);
-    rule!(quoted_path_component<&'a str>; captured_str!(s) => s);
-    rule!(path_component<&'a str>; children!(
-        [unquoted_path_component(s)] => s,
-        [quoted_path_component(s)] => s,
-    ));
-    rule!(path<PathBuf>; children!(
-        [path_component(components)..] => {
-            components.collect()
-        }
-    ));
-
-    rule_group!(local<(FilePrefix, PathBuf)>);
-
-    rule!(parent_path<(FilePrefix, PathBuf)> as local; children!(
-        [path(p)] => (FilePrefix::Parent, p)
-    ));
-    rule!(here_path<(FilePrefix, PathBuf)> as local; children!(
-        [path(p)] => (FilePrefix::Here, p)
-    ));
-    rule!(home_path<(FilePrefix, PathBuf)> as local; children!(
-        [path(p)] => (FilePrefix::Home, p)
-    ));
-    rule!(absolute_path<(FilePrefix, PathBuf)> as local; children!(
-        [path(p)] => (FilePrefix::Absolute, p)
-    ));
-
-    rule!(scheme<Scheme>; captured_str!(s) => match s {
-        "http" => Scheme::HTTP,
-        "https" => Scheme::HTTPS,
-        _ => unreachable!(),
-    });
-
-    rule!(http_raw<URL>; children!(
-        [scheme(sch), authority(auth), path(p)] => URL {
-            scheme: sch,
-            authority: auth,
-            path: p,
-            query: Option::None,
-            headers: Option::None,
-        },
-        [scheme(sch), authority(auth), path(p), query(q)] => URL {
-            scheme: sch,
-            authority: auth,
-            path: p,
-            query: Option::Some(q),
-            headers: Option::None,
-        },
-    ));
-
-    rule!(authority<String>; captured_str!(s) => s.to_owned());
-
-    rule!(query<String>; captured_str!(s) => s.to_owned());
-
-    rule!(http<URL>; children!(
-        [http_raw(url)] => url,
-        [http_raw(url), import_hashed(ih)] =>
-            URL { headers: Option::Some(Box::new(ih)), ..url },
-    ));
-
-    rule!(env<String>; children!(
-        [bash_environment_variable(s)] => s,
-        [posix_environment_variable(s)] => s,
-    ));
-    rule!(bash_environment_variable<String>; captured_str!(s) => s.to_owned());
-    rule!(posix_environment_variable<String>; captured_str!(s) => s.to_owned());
-
-    token_rule!(missing<()>);
-
-    rule!(import_type<ImportLocation>; children!(
-        [missing(_)] => {
-            ImportLocation::Missing
-        },
-        [env(e)] => {
-            ImportLocation::Env(e)
-        },
-        [http(url)] => {
-            ImportLocation::Remote(url)
-        },
-        [local((prefix, p))] => {
-            ImportLocation::Local(prefix, p)
-        },
-    ));
-
-    rule!(hash<Hash>; captured_str!(s) =>
-        Hash {
-            protocol: s.trim()[..6].to_owned(),
-            hash: s.trim()[7..].to_owned(),
-        }
-    );
-
-    rule!(import_hashed<ImportHashed>; children!(
-        [import_type(location)] =>
-            ImportHashed { location, hash: Option::None },
-        [import_type(location), hash(h)] =>
-            ImportHashed { location, hash: Option::Some(h) },
-    ));
-
-    token_rule!(Text<()>);
-
-    rule!(import<ParsedExpr> as expression; children!(
-        [import_hashed(location_hashed)] => {
-            Embed(Import {
-                mode: ImportMode::Code,
-                location_hashed
-            })
-        },
-        [import_hashed(location_hashed), Text(_)] => {
-            Embed(Import {
-                mode: ImportMode::RawText,
-                location_hashed
-            })
-        },
-    ));
-
-    token_rule!(lambda<()>);
-    token_rule!(forall<()>);
-    token_rule!(arrow<()>);
-    token_rule!(merge<()>);
-    token_rule!(if_<()>);
-    token_rule!(in_<()>);
-
-    rule!(expression<ParsedExpr> as expression; children!(
-        [lambda(()), nonreserved_label(l), expression(typ),
-                arrow(()), expression(body)] => {
-            Lam(l, rc(typ), rc(body))
-        },
-        [if_(()), expression(cond), expression(left), expression(right)] => {
-            BoolIf(rc(cond), rc(left), rc(right))
-        },
-        [let_binding(bindings).., in_(()), expression(final_expr)] => {
-            bindings.fold(
-                final_expr,
-                |acc, x| Let(x.0, x.1, x.2, rc(acc))
-            )
-        },
-        [forall(()), nonreserved_label(l), expression(typ),
-                arrow(()), expression(body)] => {
-            Pi(l, rc(typ), rc(body))
-        },
-        [expression(typ), arrow(()), expression(body)] => {
-            Pi("_".into(), rc(typ), rc(body))
-        },
-        [merge(()), expression(x), expression(y), expression(z)] => {
-            Merge(rc(x), rc(y), Option::Some(rc(z)))
-        },
-        [merge(()), expression(x), expression(y)] => {
-            Merge(rc(x), rc(y), Option::None)
-        },
-        [expression(e)] => e,
-    ));
-
-    rule!(let_binding<(Label, Option<ParsedSubExpr>, ParsedSubExpr)>; children!(
-        [nonreserved_label(name), expression(annot), expression(expr)] =>
-            (name, Option::Some(rc(annot)), rc(expr)),
-        [nonreserved_label(name), expression(expr)] =>
-            (name, Option::None, rc(expr)),
-    ));
-
-    token_rule!(List<()>);
-    token_rule!(Optional<()>);
-
-    rule!(empty_collection<ParsedExpr> as expression; children!(
-        [List(_), expression(t)] => {
-            EmptyListLit(rc(t))
-        },
-        [Optional(_), expression(t)] => {
-            EmptyOptionalLit(rc(t))
-        },
-    ));
-
-    rule!(non_empty_optional<ParsedExpr> as expression; children!(
-        [expression(x), Optional(_), expression(t)] => {
-            Annot(rc(NEOptionalLit(rc(x))), rc(t))
-        }
-    ));
-
-    rule!(import_alt_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::ImportAlt;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(or_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::BoolOr;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(plus_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::NaturalPlus;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(text_append_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::TextAppend;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(list_append_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::ListAppend;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(and_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::BoolAnd;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(combine_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::Combine;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(prefer_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::Prefer;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(combine_types_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::CombineTypes;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(times_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::NaturalTimes;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(equal_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::BoolEQ;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-    rule!(not_equal_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), expression(rest)..] => {
-            let o = crate::BinOp::BoolNE;
-            rest.fold(first, |acc, e| BinOp(o, rc(acc), rc(e)))
-        },
-    ));
-
-    rule!(annotated_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(e), expression(annot)] => {
-            Annot(rc(e), rc(annot))
-        },
-    ));
-
-    token_rule!(Some<()>);
-
-    rule!(application_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(Builtin(crate::Builtin::OptionalNone)),
-                expression(e), expression(rest)..] => {
-            app(EmptyOptionalLit(rc(e)), rest.map(rc).collect())
-        },
-        [Some(()), expression(e), expression(rest)..] => {
-            app(NEOptionalLit(rc(e)), rest.map(rc).collect())
-        },
-        [expression(first), expression(rest)..] => {
-            app(first, rest.map(rc).collect())
-        },
-    ));
-
-    rule!(selector_expression<ParsedExpr> as expression; children!(
-        [expression(e)] => e,
-        [expression(first), selector(rest)..] => {
-            rest.fold(first, |acc, e| match e {
-                Either::Left(l) => Field(rc(acc), l),
-                Either::Right(ls) => Projection(rc(acc), ls),
-            })
-        }
-    ));
-
-    rule!(selector<Either<Label, Vec<Label>>>; children!(
-        [label(l)] => Either::Left(l),
-        [labels(ls)] => Either::Right(ls),
-    ));
-
-    rule!(labels<Vec<Label>>; children!(
-        [label(ls)..] => ls.collect(),
-    ));
-
-    rule!(primitive_expression<ParsedExpr> as expression; children!(
-        [double_literal(n)] => DoubleLit(n),
-        [natural_literal(n)] => NaturalLit(n),
-        [integer_literal(n)] => IntegerLit(n),
-        [double_quote_literal(s)] => TextLit(s),
-        [single_quote_literal(s)] => TextLit(s),
-        [expression(e)] => e,
-    ));
-
-    rule!(identifier<ParsedExpr> as expression; children!(
-        [label(l), natural_literal(idx)] => {
-            let name = String::from(&l);
-            match crate::Builtin::parse(name.as_str()) {
-                Option::Some(b) => Builtin(b),
-                Option::None => match name.as_str() {
-                    "True" => BoolLit(true),
-                    "False" => BoolLit(false),
-                    "Type" => Const(crate::Const::Type),
-                    "Kind" => Const(crate::Const::Kind),
-                    "Sort" => Const(crate::Const::Sort),
-                    _ => Var(V(l, idx)),
-                }
-            }
-        },
-        [label(l)] => {
-            let name = String::from(&l);
-            match crate::Builtin::parse(name.as_str()) {
-                Option::Some(b) => Builtin(b),
-                Option::None => match name.as_str() {
-                    "True" => BoolLit(true),
-                    "False" => BoolLit(false),
-                    "Type" => Const(crate::Const::Type),
-                    "Kind" => Const(crate::Const::Kind),
-                    "Sort" => Const(crate::Const::Sort),
-                    _ => Var(V(l, 0)),
-                }
-            }
-        },
-    ));
-
-    rule!(empty_record_literal<ParsedExpr> as expression;
-        captured_str!(_) => RecordLit(BTreeMap::new())
-    );
-
-    rule!(empty_record_type<ParsedExpr> as expression;
-        captured_str!(_) => RecordType(BTreeMap::new())
-    );
-
-    rule!(non_empty_record_type_or_literal<ParsedExpr> as expression; children!(
-        [label(first_label), non_empty_record_type(rest)] => {
-            let (first_expr, mut map) = rest;
-            map.insert(first_label, rc(first_expr));
-            RecordType(map)
-        },
-        [label(first_label), non_empty_record_literal(rest)] => {
-            let (first_expr, mut map) = rest;
-            map.insert(first_label, rc(first_expr));
-            RecordLit(map)
-        },
-    ));
-
-    rule!(non_empty_record_type
-          <(ParsedExpr, BTreeMap<Label, ParsedSubExpr>)>; children!(
-        [expression(expr), record_type_entry(entries)..] => {
-            (expr, entries.collect())
-        }
-    ));
-
-    rule!(record_type_entry<(Label, ParsedSubExpr)>; children!(
-        [label(name), expression(expr)] => (name, rc(expr))
-    ));
-
-    rule!(non_empty_record_literal
-          <(ParsedExpr, BTreeMap<Label, ParsedSubExpr>)>; children!(
-        [expression(expr), record_literal_entry(entries)..] => {
-            (expr, entries.collect())
-        }
-    ));
-
-    rule!(record_literal_entry<(Label, ParsedSubExpr)>; children!(
-        [label(name), expression(expr)] => (name, rc(expr))
-    ));
-
-    rule!(union_type_or_literal<ParsedExpr> as expression; children!(
-        [empty_union_type(_)] => {
-            UnionType(BTreeMap::new())
-        },
-        [non_empty_union_type_or_literal((Option::Some((l, e)), entries))] => {
-            UnionLit(l, e, entries)
-        },
-        [non_empty_union_type_or_literal((Option::None, entries))] => {
-            UnionType(entries)
-        },
-    ));
-
-    token_rule!(empty_union_type<()>);
-
-    rule!(non_empty_union_type_or_literal
-          <(Option<(Label, ParsedSubExpr)>, BTreeMap<Label, ParsedSubExpr>)>;
-            children!(
-        [label(l), union_literal_variant_value((e, entries))] => {
-            (Option::Some((l, rc(e))), entries)
-        },
-        [label(l), union_type_or_literal_variant_type((e, rest))] => {
-            let (x, mut entries) = rest;
-            entries.insert(l, rc(e));
-            (x, entries)
-        },
-    ));
-
-    rule!(union_literal_variant_value
-          <(ParsedExpr, BTreeMap<Label, ParsedSubExpr>)>;
-            children!(
-        [expression(e), union_type_entry(entries)..] => {
-            (e, entries.collect())
-        },
-    ));
-
-    rule!(union_type_entry<(Label, ParsedSubExpr)>; children!(
-        [label(name), expression(expr)] => (name, rc(expr))
-    ));
-
-    // TODO: unary union variants
-    rule!(union_type_or_literal_variant_type
-          <(ParsedExpr,
-            (Option<(Label, ParsedSubExpr)>, BTreeMap<Label, ParsedSubExpr>))>;
-                children!(
-        [expression(e), non_empty_union_type_or_literal(rest)] => {
-            (e, rest)
-        },
-        [expression(e)] => {
-            (e, (Option::None, BTreeMap::new()))
-        },
-    ));
-
-    rule!(non_empty_list_literal<ParsedExpr> as expression; children!(
-        [expression(items)..] => NEListLit(items.map(rc).collect())
-    ));
-
-    rule!(final_expression<ParsedExpr> as expression; children!(
-        [expression(e), EOI(_eoi)] => e
-    ));
+pub fn parse_expr(s: &str) -> ParseResult<ParsedSubExpr> {
+    parse_expr_in_file(s, None)
 }
 
-pub fn parse_expr(s: &str) -> ParseResult<ParsedSubExpr> {
-    let mut pairs = DhallParser::parse(Rule::final_expression, s)?;
-    let expr = do_parse(pairs.next().unwrap())?;
-    assert_eq!(pairs.next(), None);
-    match expr {
-        ParsedValue::expression(e) => Ok(rc(e)),
-        _ => unreachable!(),
-    }
-    // Ok(rc(BoolLit(false)))
+/// Parses the contents of the given file, which its spans will refer to
+pub fn parse_expr_in_file(
+    s: &str,
+    file: Option<&Path>,
+) -> ParseResult<ParsedSubExpr> {
+    crate::descent::parse(s, file.map(|f| Rc::new(f.to_owned())))
 }
 
 #[test]
@@ -1,5 +1,6 @@
 use crate::*;
 use itertools::Itertools;
+use num_bigint::Sign;
 use std::fmt::{self, Display};
 
 impl<S, A: Display> Display for Expr<S, A> {
@@ -183,7 +184,7 @@ impl<S, A: Display> Expr<S, A> {
             BoolLit(true) => f.write_str("True")?,
             BoolLit(false) => f.write_str("False")?,
             NaturalLit(a) => a.fmt(f)?,
-            IntegerLit(a) if *a >= 0 => {
+            IntegerLit(a) if a.sign() != Sign::Minus => {
                 f.write_str("+")?;
                 a.fmt(f)?;
             }
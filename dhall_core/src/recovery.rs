@@ -0,0 +1,485 @@
+//! Parsing that keeps going after syntax errors, for editor tooling.
+//!
+//! When the input does not parse, it is split at the boundaries that can be
+//! found without a full parse: the fields of a record, the elements of a list,
+//! and `let` bindings. Each piece is then parsed on its own. A piece that
+//! still fails becomes a `Recovered::Error` placeholder, and the failure is
+//! reported as a diagnostic.
+use crate::parser::parse_expr;
+use crate::*;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What a partially parsed expression embeds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovered {
+    Import(Import),
+    /// Source that could not be parsed
+    Error,
+}
+
+impl fmt::Display for Recovered {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recovered::Import(import) => write!(f, "{}", import),
+            Recovered::Error => f.write_str("<error>"),
+        }
+    }
+}
+
+/// A syntax error found while recovering
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+pub type RecoveredSubExpr = SubExpr<Span, Recovered>;
+
+/// Parses as much of the input as possible. Returns an expression with a
+/// `Recovered::Error` in place of each part that could not be parsed, along
+/// with the errors found.
+pub fn parse_expr_recovering(s: &str) -> (RecoveredSubExpr, Vec<Diagnostic>) {
+    let mut diagnostics = vec![];
+    let e = recover(s, 0, s.len(), &mut diagnostics);
+    (e, diagnostics)
+}
+
+fn span(start: usize, end: usize) -> Span {
+    Span {
+        file: None,
+        start,
+        end,
+    }
+}
+
+fn noted(
+    start: usize,
+    end: usize,
+    e: Expr<Span, Recovered>,
+) -> RecoveredSubExpr {
+    rc(ExprF::Note(span(start, end), rc(e)))
+}
+
+fn error(
+    start: usize,
+    end: usize,
+    message: String,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> RecoveredSubExpr {
+    diagnostics.push(Diagnostic {
+        span: span(start, end),
+        message,
+    });
+    noted(start, end, ExprF::Embed(Recovered::Error))
+}
+
+/// Moves the spans of an expression parsed from `src[base..]` so that they
+/// refer to `src`
+fn relocate(e: &SubExpr<Span, Import>, base: usize) -> RecoveredSubExpr {
+    rc(e.as_ref().map_ref(
+        |e| relocate(e, base),
+        |_, e| relocate(e, base),
+        |s| span(s.start + base, s.end + base),
+        |import| Recovered::Import(import.clone()),
+        Label::clone,
+    ))
+}
+
+fn recover(
+    src: &str,
+    start: usize,
+    end: usize,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> RecoveredSubExpr {
+    let (start, end) = trim(src, start, end);
+    let err = match parse_expr(&src[start..end]) {
+        Ok(e) => return relocate(&e, start),
+        Err(err) => err,
+    };
+    let text = &src[start..end];
+    let encloses = |open, close| {
+        text.starts_with(open)
+            && matching_close(src, start, end) == Some(end - 1)
+            && text.ends_with(close)
+    };
+    if encloses('{', '}') {
+        recover_record(src, start, end, diagnostics)
+    } else if encloses('[', ']') {
+        let elems = split(src, start + 1, end - 1, ',')
+            .into_iter()
+            .map(|(s, e)| recover(src, s, e, diagnostics))
+            .collect();
+        noted(start, end, ExprF::NEListLit(elems))
+    } else if encloses('(', ')') {
+        recover(src, start + 1, end - 1, diagnostics)
+    } else if keyword_at(src, start, "let") {
+        recover_let(src, start, end, diagnostics)
+    } else {
+        let (s, e) = (err.span.start, err.span.end);
+        error(start + s, start + e, err.message, diagnostics)
+    }
+}
+
+fn recover_record(
+    src: &str,
+    start: usize,
+    end: usize,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> RecoveredSubExpr {
+    let mut fields = vec![];
+    for (s, e) in split(src, start + 1, end - 1, ',') {
+        let sep = scan(src, s, e)
+            .into_iter()
+            .find(|&(_, c, depth)| depth == 0 && (c == '=' || c == ':'));
+        let (i, c) = match sep {
+            Some((i, c, _)) => (i, c),
+            None => {
+                let message = "expected a record field".to_owned();
+                diagnostics.push(Diagnostic {
+                    span: span(s, e),
+                    message,
+                });
+                continue;
+            }
+        };
+        let label = match parse_label(src, s, i) {
+            Some(label) => label,
+            None => {
+                let message = "expected a field name".to_owned();
+                diagnostics.push(Diagnostic {
+                    span: span(s, i),
+                    message,
+                });
+                continue;
+            }
+        };
+        let x = recover(src, i + c.len_utf8(), e, diagnostics);
+        let (s, e) = trim(src, s, e);
+        fields.push((s, e, label, c, x));
+    }
+    // A mix of both kinds of fields is most likely a typo in the fewer ones,
+    // which are replaced with placeholders
+    let values = fields.iter().filter(|f| f.3 == '=').count();
+    let sep = if values * 2 >= fields.len() { '=' } else { ':' };
+    let mut kts = BTreeMap::new();
+    for (s, e, label, c, x) in fields {
+        let x = if c == sep {
+            x
+        } else {
+            let message = format!("expected `{}` like the other fields", sep);
+            error(s, e, message, diagnostics)
+        };
+        if kts.contains_key(&label) {
+            let message = format!("duplicate field `{}`", label);
+            diagnostics.push(Diagnostic {
+                span: span(s, e),
+                message,
+            });
+        } else {
+            kts.insert(label, x);
+        }
+    }
+    if sep == '=' {
+        noted(start, end, ExprF::RecordLit(kts))
+    } else {
+        noted(start, end, ExprF::RecordType(kts))
+    }
+}
+
+fn recover_let(
+    src: &str,
+    start: usize,
+    end: usize,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> RecoveredSubExpr {
+    let mut bindings = vec![];
+    let mut pos = start;
+    let body_start = loop {
+        // `pos` is at a `let` keyword
+        let binding_start = pos + "let".len();
+        let eq = scan(src, binding_start, end)
+            .into_iter()
+            .find(|&(_, c, depth)| depth == 0 && c == '=')
+            .map(|(i, _, _)| i);
+        let eq = match eq {
+            Some(eq) => eq,
+            None => {
+                let message = "expected `=` in `let` binding".to_owned();
+                return error(start, end, message, diagnostics);
+            }
+        };
+        let (value_end, next) = match end_of_let_value(src, eq + 1, end) {
+            Some(found) => found,
+            None => {
+                let message = "expected `in` after `let` binding".to_owned();
+                return error(start, end, message, diagnostics);
+            }
+        };
+        let annot = scan(src, binding_start, eq)
+            .into_iter()
+            .find(|&(_, c, depth)| depth == 0 && c == ':')
+            .map(|(i, _, _)| i);
+        let label_end = annot.unwrap_or(eq);
+        let label = match parse_label(src, binding_start, label_end) {
+            Some(label) => label,
+            None => {
+                let message = "expected a variable name".to_owned();
+                return error(start, end, message, diagnostics);
+            }
+        };
+        let annot = annot.map(|i| recover(src, i + 1, eq, diagnostics));
+        let value = recover(src, eq + 1, value_end, diagnostics);
+        bindings.push((pos, label, annot, value));
+        match next {
+            Keyword::Let => pos = value_end,
+            Keyword::In => break value_end + "in".len(),
+        }
+    };
+    let body = recover(src, body_start, end, diagnostics);
+    bindings
+        .into_iter()
+        .rev()
+        .fold(body, |body, (pos, label, annot, value)| {
+            noted(pos, end, ExprF::Let(label, annot, value, body))
+        })
+}
+
+enum Keyword {
+    Let,
+    In,
+}
+
+/// Finds where the value of a `let` binding starting at `start` ends: at
+/// either the `let` of the next binding or the `in` of the whole expression
+fn end_of_let_value(
+    src: &str,
+    start: usize,
+    end: usize,
+) -> Option<(usize, Keyword)> {
+    // Number of `let`s nested in the value that still wait for their `in`
+    let mut open = 0;
+    let mut prev = '=';
+    for (i, c, depth) in scan(src, start, end) {
+        if depth == 0 && !is_label_char(prev) {
+            if keyword_at(src, i, "in") {
+                if open == 0 {
+                    return Some((i, Keyword::In));
+                }
+                open -= 1;
+            } else if keyword_at(src, i, "let") {
+                // A `let` right after a complete expression starts the next
+                // binding; anywhere else it starts a nested expression
+                if open == 0 && ends_expression(src, start, i) {
+                    return Some((i, Keyword::Let));
+                }
+                if open == 0 || !ends_expression(src, start, i) {
+                    open += 1;
+                }
+            }
+        }
+        prev = c;
+    }
+    None
+}
+
+/// Whether the last non-blank character before `pos` can end an expression
+fn ends_expression(src: &str, start: usize, pos: usize) -> bool {
+    match src[start..pos].trim_end().chars().last() {
+        Some(c) => is_label_char(c) || ")]}\"'`".contains(c),
+        None => false,
+    }
+}
+
+fn is_label_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '/'
+}
+
+fn keyword_at(src: &str, pos: usize, keyword: &str) -> bool {
+    let rest = &src[pos..];
+    rest.starts_with(keyword)
+        && !rest[keyword.len()..].starts_with(is_label_char)
+        && !src[..pos].ends_with(is_label_char)
+}
+
+fn parse_label(src: &str, start: usize, end: usize) -> Option<Label> {
+    let (start, end) = trim(src, start, end);
+    let s = &src[start..end];
+    if s.len() > 2 && s.starts_with('`') && s.ends_with('`') {
+        return Some(Label::from(&s[1..s.len() - 1]));
+    }
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {}
+        _ => return None,
+    }
+    if chars.all(is_label_char) {
+        Some(Label::from(s))
+    } else {
+        None
+    }
+}
+
+fn trim(src: &str, start: usize, end: usize) -> (usize, usize) {
+    let s = &src[start..end];
+    let start = start + (s.len() - s.trim_start().len());
+    let end = start + s.trim().len();
+    (start, end)
+}
+
+/// Splits `src[start..end]` at the separators that are not nested in
+/// brackets, strings or comments
+fn split(
+    src: &str,
+    start: usize,
+    end: usize,
+    separator: char,
+) -> Vec<(usize, usize)> {
+    let mut pieces = vec![];
+    let mut piece_start = start;
+    for (i, c, depth) in scan(src, start, end) {
+        if depth == 0 && c == separator {
+            pieces.push((piece_start, i));
+            piece_start = i + c.len_utf8();
+        }
+    }
+    pieces.push((piece_start, end));
+    pieces
+}
+
+/// The offset of the bracket that closes the one at `start`
+fn matching_close(src: &str, start: usize, end: usize) -> Option<usize> {
+    scan(src, start, end)
+        .into_iter()
+        .skip(1)
+        .find(|&(_, c, depth)| depth == 0 && ")]}".contains(c))
+        .map(|(i, _, _)| i)
+}
+
+/// The characters of `src[start..end]` that are outside of strings and
+/// comments, with their offset and the depth of brackets they are nested in.
+/// Closing brackets have the depth of the matching opening one.
+fn scan(src: &str, start: usize, end: usize) -> Vec<(usize, char, usize)> {
+    let s = &src[start..end];
+    let mut chars = vec![];
+    let mut depth = 0usize;
+    let mut i = 0;
+    while let Some(c) = s[i..].chars().next() {
+        let rest = &s[i..];
+        if rest.starts_with("--") {
+            i += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        }
+        if rest.starts_with("{-") {
+            i += skip_block_comment(rest);
+            continue;
+        }
+        if rest.starts_with("''") {
+            i += skip_until(rest, 2, "''");
+            continue;
+        }
+        match c {
+            '"' => {
+                i += skip_double_quoted(rest);
+                continue;
+            }
+            '`' => {
+                i += skip_until(rest, 1, "`");
+                continue;
+            }
+            '(' | '[' | '{' => {
+                chars.push((start + i, c, depth));
+                depth += 1;
+            }
+            ')' | ']' | '}' => {
+                depth = depth.saturating_sub(1);
+                chars.push((start + i, c, depth));
+            }
+            _ => chars.push((start + i, c, depth)),
+        }
+        i += c.len_utf8();
+    }
+    chars
+}
+
+/// The length of `s` up to and including the first `end` after `skip` bytes
+fn skip_until(s: &str, skip: usize, end: &str) -> usize {
+    match s[skip..].find(end) {
+        Some(i) => skip + i + end.len(),
+        None => s.len(),
+    }
+}
+
+fn skip_block_comment(s: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < s.len() {
+        if s[i..].starts_with("{-") {
+            depth += 1;
+            i += 2;
+        } else if s[i..].starts_with("-}") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += s[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    s.len()
+}
+
+fn skip_double_quoted(s: &str) -> usize {
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recover(s: &str) -> (String, Vec<String>) {
+        let (e, diagnostics) = parse_expr_recovering(s);
+        let diagnostics = diagnostics
+            .iter()
+            .map(|d| s[d.span.start..d.span.end].to_owned())
+            .collect();
+        (e.to_string(), diagnostics)
+    }
+
+    #[test]
+    fn test_recovery() {
+        assert_eq!(recover("{ x = 1 }"), ("{ x = 1 }".to_owned(), vec![]));
+        assert_eq!(
+            recover("{ x = 1, y = +, z = [True, if] }").0,
+            "{ x = 1, y = <error>, z = [True, <error>] }"
+        );
+        let (e, errors) = recover("{ x = 1, , y = 2 }");
+        assert_eq!(e, "{ x = 1, y = 2 }");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            recover("let x = 1 let y = ) in { a = x, b = y }").0,
+            "let x = 1 in let y = <error> in { a = x, b = y }"
+        );
+        assert_eq!(
+            recover("let x = let y = 1 in y in x +").0,
+            "let x = let y = 1 in y in <error>"
+        );
+        let (e, errors) = recover("{ x = 1, y : Bool, x = 2 }");
+        assert_eq!(e, "{ x = 1, y = <error> }");
+        assert_eq!(errors, vec!["y : Bool", "x = 2"]);
+        let (e, errors) = recover("{ x : Bool, y = 1, z : Natural }");
+        assert_eq!(e, "{ x : Bool, y : <error>, z : Natural }");
+        assert_eq!(errors, vec!["y = 1"]);
+    }
+}
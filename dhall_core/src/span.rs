@@ -0,0 +1,37 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// A range of bytes in a source file. The parser attaches one to every
+/// expression as a note.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// The file the source comes from, or `None` for a string
+    pub file: Option<Rc<PathBuf>>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The line and column of the start of the span in the given source,
+    /// both starting from 1
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        (line, before[line_start..].chars().count() + 1)
+    }
+    /// The file the span comes from, or `(input)` for a string
+    pub fn file_name(&self) -> String {
+        match &self.file {
+            Some(file) => file.display().to_string(),
+            None => "(input)".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}-{}", self.file_name(), self.start, self.end)
+    }
+}
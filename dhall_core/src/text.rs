@@ -1,7 +1,7 @@
 use std::iter::FromIterator;
 use std::ops::Add;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct InterpolatedText<SubExpr> {
     head: String,
     tail: Vec<(SubExpr, String)>,
@@ -27,7 +27,7 @@ impl<SubExpr> From<String> for InterpolatedText<SubExpr> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum InterpolatedTextContents<SubExpr> {
     Text(String),
     Expr(SubExpr),
@@ -65,6 +65,17 @@ impl<SubExpr> InterpolatedText<SubExpr> {
         }
     }
 
+    /// The number of interpolated expressions
+    pub fn interpolations(&self) -> usize {
+        self.tail.len()
+    }
+
+    /// The number of bytes of text, not counting the interpolated
+    /// expressions
+    pub fn text_len(&self) -> usize {
+        self.head.len() + self.tail.iter().map(|(_, s)| s.len()).sum::<usize>()
+    }
+
     pub fn iter<'a>(
         &'a self,
     ) -> impl Iterator<Item = InterpolatedTextContents<SubExpr>> + 'a